@@ -1,13 +1,64 @@
 #[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    coins, to_binary, BankMsg, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult,
-    Uint128,
+    coin, coins, from_json, to_json_binary, Addr, Attribute, BankMsg, Binary, BlockInfo, Coin,
+    CosmosMsg, Decimal, Deps, DepsMut, DistributionMsg, Env, Event, IbcBasicResponse, IbcChannelCloseMsg,
+    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcChannelOpenResponse, IbcOrder, IbcPacket,
+    IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse,
+    MessageInfo, Never, Order, Reply, Response, StakingMsg, StdError, StdResult, Storage, SubMsg,
+    SubMsgResult, Timestamp, Uint128, WasmMsg,
 };
+use cw_storage_plus::Bound;
+use cw_utils::{parse_reply_execute_data, Duration, Expiration};
+use sha2::{Digest, Sha256};
 
 use crate::error::ContractError;
-use crate::msg::{BalanceResp, ExecuteMsg, InstantiateMsg, OwnerResp, QueryMsg,FeeResp};
-use crate::state::{BALANCE, COIN_DENOM, FEE, OWNER};
+use crate::events::{
+    FundRemoteEscrowEvent, RefundRemoteEscrowEvent, SetIbcCounterpartyEvent, DelegationEvent, RedelegationEvent, HarvestRewardsEvent, ClaimRewardsEvent, SetStakingEvent, RebalanceEvent, ClaimWithdrawalEvent, ApproveReleaseEvent, AcceptanceWindowEvent, ForwardingEvent, AllowanceEvent, SplitCreatedEvent, SplitLockedEvent, SplitUpdatedEvent, AcceptanceEvent, BatchTransferEvent, EscrowCreatedEvent, EscrowReleaseEvent, CancelScheduleEvent, CancelStreamEvent, CancelTransferEvent, ClaimEvent, ForwardEvent, HashLockCreatedEvent, IbcRefundEvent, IbcWithdrawEvent, InternalTransferEvent, InvoiceCreatedEvent, InvoicePaidEvent, SplitDistributionEvent, SplitPaymentEvent,
+    SplitTransferEvent, HashLockReleasedEvent, ReclaimEvent, RefundEscrowEvent, RefundHashLockEvent, ScheduleCreatedEvent, ScheduledTransferEvent, StreamFundedEvent, WithdrawEvent, WithdrawFromEvent,
+};
+use crate::msg::{
+    AllAllowancesResp, AllowanceResp, BalanceResp, DelegationsResp, UserDelegation, BatchFeeMode, BatchTransferItem, CancelFeeMode, ClaimsResp, HashLocksResp, StreamResp, Vesting, VestingResp, ExecuteMsg, FeeResp,
+    ForwardPreference, ForwardingResp, HookFailureMode, IbcAck, IbcLifecycleComplete, InstantiateMsg, ReceiverMsg,
+    InternalFeeMode, OwnerResp,
+    Payee, PendingRewardsResp, QueryMsg, QueuedWithdrawalResp, RemoteEscrowResp, SplitPacket, RecipientAmount, StakingResp, SimulateTransferResp, SpenderAllowance, SplitRecipient, SplitsResp,
+    SudoMsg, TransfersResp,
+};
+use crate::state::{
+    acceptances, claims, splits, transfers, Escrow, Forwarding, HashLock, Invoice, PendingClaim, PendingRelease, PendingMap, Schedule, Stream, VestingGrant, Split, SplitPayee, SplitShare, TransferRecord, ACCEPTANCE_WINDOWS, ALLOWANCES, BALANCE, BATCH_FEE_MODE, CancellableCredit, CANCELLABLE_CREDITS, STREAMED, STREAMS, STREAM_COUNT, VESTING,
+    CANCEL_FEE_MODE, COIN_DENOM, ESCROWS, ESCROW_COUNT, FEE, HASH_LOCKS, HASH_LOCK_COUNT, INVOICES,
+    INVOICE_COUNT, KEEPER_BOUNTY, SCHEDULES, SCHEDULE_COUNT, StakingConfig, DELEGATED,
+    QUEUED_TOTAL, QUEUED_WITHDRAWALS, STAKING, REWARDS_BOOKED, DELEGATION_REWARD_INDEX, DELEGATION_REWARD_PER_SHARE, RewardInfo, REWARDS, REWARD_PER_SHARE,
+    RESERVED, TOTAL_BALANCE, USER_DELEGATED, USER_DELEGATIONS, QueuedWithdrawal, UNBONDING_PERIOD, UNBONDING_UNTIL, WITHDRAWAL_QUEUE,
+    WITHDRAWAL_QUEUE_COUNT, IbcWithdrawal, IBC_CHANNELS, IBC_SENDING, IBC_WITHDRAWALS,
+    IBC_COUNTERPARTY_PORT, RemoteEscrow, REMOTE_ESCROWS, HOOK_FAILURE_MODE,
+    FORWARDING, INTERNAL_FEE_MODE, LEGACY_ATTRIBUTES, OWNER, SPLIT_COUNT, TRANSFER_COUNT,
+};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+/// How many levels of splits-within-splits a payment may pass through.
+const MAX_SPLIT_DEPTH: u32 = 5;
+/// How many unfinished vesting grants an address may hold, bounding the work
+/// of releasing them whenever it spends from its balance.
+const MAX_VESTING_GRANTS: usize = 30;
+/// How many unfinished streams an address may be receiving, for the same
+/// reason.
+const MAX_STREAMS: usize = 30;
+/// Type url of the ICS20 transfer sent by `WithdrawIbc`.
+const MSG_TRANSFER_TYPE_URL: &str = "/ibc.applications.transfer.v1.MsgTransfer";
+/// Reply id for the ICS20 transfer sent by `WithdrawIbc`.
+const IBC_TRANSFER_REPLY_ID: u64 = 2;
+/// Reply id for `SplitReceived` hooks whose failure is ignored.
+const HOOK_REPLY_ID: u64 = 3;
+/// Reply id for the `ReceiveSplit` run for each split packet.
+const RECEIVE_SPLIT_REPLY_ID: u64 = 4;
+/// Unbonding period assumed when none is given at instantiation.
+const DEFAULT_UNBONDING_PERIOD: Duration = Duration::Time(21 * 24 * 60 * 60);
+/// Version spoken on channels opened to this contract.
+pub const IBC_VERSION: &str = "split-1";
 
+#[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
@@ -17,15 +68,140 @@ pub fn instantiate(
     OWNER.save(deps.storage, &deps.api.addr_validate(&msg.owner)?)?;
     COIN_DENOM.save(deps.storage, &msg.coin_denom)?;
     FEE.save(deps.storage, &msg.fee)?;
+    LEGACY_ATTRIBUTES.save(deps.storage, &msg.legacy_attributes.unwrap_or(false))?;
+    BATCH_FEE_MODE.save(deps.storage, &msg.batch_fee_mode.unwrap_or_default())?;
+    INTERNAL_FEE_MODE.save(deps.storage, &msg.internal_fee_mode.unwrap_or_default())?;
+    CANCEL_FEE_MODE.save(deps.storage, &msg.cancel_fee_mode.unwrap_or_default())?;
+    KEEPER_BOUNTY.save(deps.storage, &msg.keeper_bounty.unwrap_or_default())?;
+    HOOK_FAILURE_MODE.save(deps.storage, &msg.hook_failure_mode.unwrap_or_default())?;
+    UNBONDING_PERIOD.save(
+        deps.storage,
+        &msg.unbonding_period.unwrap_or(DEFAULT_UNBONDING_PERIOD),
+    )?;
+    IBC_COUNTERPARTY_PORT.save(deps.storage, &msg.ibc_counterparty_port.unwrap_or_default())?;
 
     Ok(Response::new())
 }
 
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+/// Every action emits a typed event. Contracts instantiated with
+/// `legacy_attributes` also get the old flat attributes on the response.
+fn event_response(
+    storage: &dyn Storage,
+    action: &str,
+    event: Event,
+    legacy_attributes: Vec<Attribute>,
+) -> StdResult<Response> {
+    let res = Response::new().add_event(event);
+    if LEGACY_ATTRIBUTES.may_load(storage)?.unwrap_or(false) {
+        Ok(res.add_attributes(legacy_attributes))
+    } else {
+        Ok(res.add_attribute("action", action))
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::Owner {} => to_binary(&query_owner(deps)?),
-        QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
-        QueryMsg::Fee {} => to_binary(&query_fee(deps)?),
+        QueryMsg::Owner {} => to_json_binary(&query_owner(deps)?),
+        QueryMsg::Balance { address, height } => {
+            to_json_binary(&query_balance(deps, env, address, height)?)
+        }
+        QueryMsg::Fee {} => to_json_binary(&query_fee(deps)?),
+        QueryMsg::TransfersBySender {
+            address,
+            start_after,
+            limit,
+        } => to_json_binary(&query_transfers_by_sender(deps, address, start_after, limit)?),
+        QueryMsg::TransfersByRecipient {
+            address,
+            start_after,
+            limit,
+        } => to_json_binary(&query_transfers_by_recipient(deps, address, start_after, limit)?),
+        QueryMsg::SimulateTransfer {
+            transfer_amount,
+            recipients,
+        } => to_json_binary(&query_simulate_transfer(
+            deps,
+            transfer_amount,
+            recipients,
+        )?),
+        QueryMsg::Split { split_id } => to_json_binary(&splits().load(deps.storage, split_id)?),
+        QueryMsg::Splits {
+            owner,
+            start_after,
+            limit,
+        } => to_json_binary(&query_splits(deps, owner, start_after, limit)?),
+        QueryMsg::Allowance { owner, spender } => {
+            to_json_binary(&query_allowance(deps, owner, spender)?)
+        }
+        QueryMsg::AllAllowances {
+            owner,
+            start_after,
+            limit,
+        } => to_json_binary(&query_all_allowances(deps, owner, start_after, limit)?),
+        QueryMsg::Forwarding { address } => to_json_binary(&query_forwarding(deps, address)?),
+        QueryMsg::Vesting { recipient } => to_json_binary(&query_vesting(deps, env, recipient)?),
+        QueryMsg::Stream { stream_id } => to_json_binary(&query_stream(deps, env, stream_id)?),
+        QueryMsg::HashLock { lock_id } => to_json_binary(&HASH_LOCKS.load(deps.storage, lock_id)?),
+        QueryMsg::HashLocks { start_after, limit } => {
+            to_json_binary(&query_hash_locks(deps, start_after, limit)?)
+        }
+        QueryMsg::Escrow { escrow_id } => to_json_binary(&ESCROWS.load(deps.storage, escrow_id)?),
+        QueryMsg::Invoice { invoice_id } => to_json_binary(&INVOICES.load(deps.storage, invoice_id)?),
+        QueryMsg::Staking {} => to_json_binary(&query_staking(deps)?),
+        QueryMsg::QueuedWithdrawal { address } => {
+            to_json_binary(&query_queued_withdrawal(deps, address)?)
+        }
+        QueryMsg::Delegations { address } => to_json_binary(&query_delegations(deps, address)?),
+        QueryMsg::RemoteEscrow {
+            channel_id,
+            remote_sender,
+        } => {
+            let escrow = REMOTE_ESCROWS.may_load(deps.storage, (&channel_id, &remote_sender))?;
+            to_json_binary(&RemoteEscrowResp {
+                amount: escrow.as_ref().map(|escrow| escrow.amount).unwrap_or_default(),
+                funder: escrow.map(|escrow| escrow.funder.into_string()),
+            })
+        }
+        QueryMsg::PendingRewards { address } => {
+            to_json_binary(&query_pending_rewards(deps, address)?)
+        }
+        QueryMsg::Schedule { schedule_id } => {
+            to_json_binary(&SCHEDULES.load(deps.storage, schedule_id)?)
+        }
+        QueryMsg::PendingClaims {
+            recipient,
+            start_after,
+            limit,
+        } => to_json_binary(&query_pending_claims(
+            deps,
+            env,
+            recipient,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::PendingAcceptances {
+            recipient,
+            start_after,
+            limit,
+        } => to_json_binary(&query_pending_acceptances(
+            deps,
+            env,
+            recipient,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ExpiredClaims {
+            sender,
+            start_after,
+            limit,
+        } => to_json_binary(&query_expired_claims(
+            deps,
+            env,
+            sender,
+            start_after,
+            limit,
+        )?),
     }
 }
 
@@ -38,14 +214,348 @@ pub fn query_fee(deps: Deps) -> StdResult<FeeResp> {
     Ok(FeeResp { fee })
 }
 
-pub fn query_balance(deps: Deps, address: String) -> StdResult<BalanceResp> {
+/// The current balance includes whatever has streamed to `address` but not
+/// yet been moved into the ledger.
+pub fn query_balance(
+    deps: Deps,
+    env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<BalanceResp> {
     let address = deps.api.addr_validate(&address)?;
-    let balance = BALANCE
-        .may_load(deps.storage, &address)?
-        .unwrap_or_default();
+    let balance = match height {
+        Some(height) => BALANCE.may_load_at_height(deps.storage, &address, height)?,
+        None => {
+            let (_, streamed) = stream_accruals(deps.storage, &address, env.block.time)?;
+            let vested = vesting_accruals(deps.storage, &address, env.block.time)?;
+            let balance = BALANCE.may_load(deps.storage, &address)?.unwrap_or_default();
+            Some(balance + streamed + vested)
+        }
+    }
+    .unwrap_or_default();
     Ok(BalanceResp { balance })
 }
 
+pub fn query_transfers_by_sender(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<TransfersResp> {
+    let address = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let transfers = transfers()
+        .idx
+        .sender
+        .prefix(address)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, transfer)| transfer))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(TransfersResp { transfers })
+}
+
+pub fn query_transfers_by_recipient(
+    deps: Deps,
+    address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<TransfersResp> {
+    let address = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    // Take a full page from each recipient slot, then merge them by id.
+    let indexes = transfers().idx;
+    let mut transfers = vec![];
+    for index in [&indexes.recipient_1, &indexes.recipient_2] {
+        for item in index
+            .prefix(address.clone())
+            .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+            .take(limit)
+        {
+            transfers.push(item?.1);
+        }
+    }
+    transfers.sort_by_key(|transfer: &TransferRecord| transfer.id);
+    transfers.dedup_by_key(|transfer| transfer.id);
+    transfers.truncate(limit);
+    Ok(TransfersResp { transfers })
+}
+
+/// Lists splits by id, optionally only those owned by `owner`.
+pub fn query_splits(
+    deps: Deps,
+    owner: Option<String>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<SplitsResp> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let splits = match owner {
+        Some(owner) => splits()
+            .idx
+            .owner
+            .prefix(deps.api.addr_validate(&owner)?)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, split)| split))
+            .collect::<StdResult<Vec<_>>>()?,
+        None => splits()
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, split)| split))
+            .collect::<StdResult<Vec<_>>>()?,
+    };
+    Ok(SplitsResp { splits })
+}
+
+pub fn query_allowance(deps: Deps, owner: String, spender: String) -> StdResult<AllowanceResp> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let spender = deps.api.addr_validate(&spender)?;
+    let allowance = ALLOWANCES
+        .may_load(deps.storage, (&owner, &spender))?
+        .unwrap_or_default();
+    Ok(AllowanceResp {
+        allowance: allowance.allowance,
+        expires: allowance.expires,
+    })
+}
+
+pub fn query_all_allowances(
+    deps: Deps,
+    owner: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<AllAllowancesResp> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|spender| deps.api.addr_validate(&spender)).transpose()?;
+    let allowances = ALLOWANCES
+        .prefix(&owner)
+        .range(deps.storage, start_after.as_ref().map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(spender, allowance)| SpenderAllowance {
+                spender: spender.to_string(),
+                allowance: allowance.allowance,
+                expires: allowance.expires,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllAllowancesResp { allowances })
+}
+
+pub fn query_forwarding(deps: Deps, address: String) -> StdResult<ForwardingResp> {
+    let address = deps.api.addr_validate(&address)?;
+    let forwarding = FORWARDING.may_load(deps.storage, &address)?;
+    Ok(ForwardingResp { forwarding })
+}
+
+pub fn query_staking(deps: Deps) -> StdResult<StakingResp> {
+    Ok(StakingResp {
+        config: STAKING.may_load(deps.storage)?,
+        delegated: DELEGATED.may_load(deps.storage)?.unwrap_or_default(),
+        queued: QUEUED_TOTAL.may_load(deps.storage)?.unwrap_or_default(),
+    })
+}
+
+pub fn query_queued_withdrawal(deps: Deps, address: String) -> StdResult<QueuedWithdrawalResp> {
+    let address = deps.api.addr_validate(&address)?;
+    let amount = QUEUED_WITHDRAWALS.may_load(deps.storage, &address)?.unwrap_or_default();
+    Ok(QueuedWithdrawalResp { amount })
+}
+
+pub fn query_delegations(deps: Deps, address: String) -> StdResult<DelegationsResp> {
+    let address = deps.api.addr_validate(&address)?;
+    let delegations = USER_DELEGATIONS
+        .prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (validator, amount) = item?;
+            Ok(UserDelegation { validator, amount })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(DelegationsResp { delegations })
+}
+
+pub fn query_pending_rewards(deps: Deps, address: String) -> StdResult<PendingRewardsResp> {
+    let address = deps.api.addr_validate(&address)?;
+    let balance = BALANCE.may_load(deps.storage, &address)?.unwrap_or_default();
+    let info = REWARDS.may_load(deps.storage, &address)?.unwrap_or_default();
+    let per_share = REWARD_PER_SHARE.may_load(deps.storage)?.unwrap_or_default();
+    let mut amount = info.pending + balance * (per_share - info.index);
+    for item in USER_DELEGATIONS.prefix(&address).range(deps.storage, None, None, Order::Ascending) {
+        let (validator, delegated) = item?;
+        let per_share = DELEGATION_REWARD_PER_SHARE
+            .may_load(deps.storage, &validator)?
+            .unwrap_or_default();
+        let index = DELEGATION_REWARD_INDEX
+            .may_load(deps.storage, (&address, &validator))?
+            .unwrap_or_default();
+        amount += delegated * (per_share - index);
+    }
+    Ok(PendingRewardsResp { amount })
+}
+
+pub fn query_vesting(deps: Deps, env: Env, recipient: String) -> StdResult<VestingResp> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let mut resp = VestingResp {
+        total: Uint128::zero(),
+        vested: Uint128::zero(),
+        withdrawn: Uint128::zero(),
+        locked: Uint128::zero(),
+    };
+    for item in VESTING.prefix(&recipient).range(deps.storage, None, None, Order::Ascending) {
+        let (_, grant) = item?;
+        let vested = vested_amount(&grant, env.block.time);
+        resp.total += grant.total;
+        resp.vested += vested;
+        resp.withdrawn += grant.released;
+        resp.locked += grant.total - vested;
+    }
+    Ok(resp)
+}
+
+pub fn query_stream(deps: Deps, env: Env, stream_id: u64) -> StdResult<StreamResp> {
+    let stream = STREAMS.load(deps.storage, stream_id)?;
+    let per_second = stream.rate_per_second * Uint128::from(stream.recipients.len() as u64);
+    let streamed = per_second * Uint128::from(streamed_seconds(&stream, env.block.time));
+    let remaining = per_second * Uint128::from(streamed_seconds(&stream, stream.end)) - streamed;
+    Ok(StreamResp {
+        stream,
+        streamed,
+        remaining,
+    })
+}
+
+pub fn query_hash_locks(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<HashLocksResp> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let hash_locks = HASH_LOCKS
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, hash_lock)| hash_lock))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(HashLocksResp { hash_locks })
+}
+
+/// Claims still waiting for `recipient`, by transfer id.
+pub fn query_pending_claims(
+    deps: Deps,
+    env: Env,
+    recipient: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ClaimsResp> {
+    pending_for_recipient(claims(), deps, env, recipient, start_after, limit)
+}
+
+/// Transfers still waiting for `recipient` to accept them, by transfer id.
+pub fn query_pending_acceptances(
+    deps: Deps,
+    env: Env,
+    recipient: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ClaimsResp> {
+    pending_for_recipient(acceptances(), deps, env, recipient, start_after, limit)
+}
+
+fn pending_for_recipient(
+    pending: PendingMap,
+    deps: Deps,
+    env: Env,
+    recipient: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ClaimsResp> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let claims = pending
+        .idx
+        .recipient
+        .prefix(recipient)
+        .range(
+            deps.storage,
+            claims_start(start_after),
+            None,
+            Order::Ascending,
+        )
+        .map(|item| item.map(|(_, claim)| claim))
+        .filter(|claim| !matches!(claim, Ok(claim) if claim.expires.is_expired(&env.block)))
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(ClaimsResp { claims })
+}
+
+/// Claims from transfers by `sender` that have expired and can be reclaimed.
+pub fn query_expired_claims(
+    deps: Deps,
+    env: Env,
+    sender: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ClaimsResp> {
+    let sender = deps.api.addr_validate(&sender)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let claims = claims()
+        .idx
+        .sender
+        .prefix(sender)
+        .range(
+            deps.storage,
+            claims_start(start_after),
+            None,
+            Order::Ascending,
+        )
+        .map(|item| item.map(|(_, claim)| claim))
+        .filter(|claim| !matches!(claim, Ok(claim) if !claim.expires.is_expired(&env.block)))
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(ClaimsResp { claims })
+}
+
+/// Claims are keyed by (transfer id, recipient); the smallest key of the next
+/// transfer starts the page.
+fn claims_start<'a>(start_after: Option<u64>) -> Option<Bound<'a, (u64, Addr)>> {
+    start_after.map(|transfer_id| Bound::inclusive((transfer_id + 1, Addr::unchecked(""))))
+}
+
+/// Runs the `Transfer` calculation without touching state. A transfer that
+/// would fail returns the same error as a query error.
+pub fn query_simulate_transfer(
+    deps: Deps,
+    transfer_amount: Uint128,
+    recipients: Vec<String>,
+) -> StdResult<SimulateTransferResp> {
+    let fee = FEE.load(deps.storage)?;
+    let recipients: [String; 2] = recipients.try_into().map_err(|_| {
+        StdError::generic_err(ContractError::InvalidRecipientCount {}.to_string())
+    })?;
+    let plan = match plan_transfer(deps, transfer_amount, fee, None, &recipients) {
+        Ok(plan) => plan,
+        Err(ContractError::Std(err)) => return Err(err),
+        Err(err) => return Err(StdError::generic_err(err.to_string())),
+    };
+    Ok(SimulateTransferResp {
+        fee: plan.fee,
+        recipients: plan
+            .recipients
+            .into_iter()
+            .map(|address| RecipientAmount {
+                address: address.to_string(),
+                amount: plan.recipient_amount,
+            })
+            .collect(),
+        sender_charged: plan.sender_charged,
+        refund: plan.refund,
+    })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
     env: Env,
@@ -57,8 +567,175 @@ pub fn execute(
             transfer_amount,
             recipient_1,
             recipient_2,
-        } => execute_transfer(deps, env, info, transfer_amount, recipient_1, recipient_2),
+            claim_deadline,
+            cancel_window,
+            vesting,
+            msg,
+        } => {
+            let terms = TransferTerms {
+                claim_deadline,
+                cancel_deadline: cancel_window.map(|window| window.after(&env.block)),
+                vesting,
+            };
+            let recipients = [recipient_1, recipient_2];
+            execute_transfer(deps, env, info, transfer_amount, recipients, terms, msg)
+        }
         ExecuteMsg::Withdraw { amount } => execute_withdraw(deps, env, info, amount),
+        ExecuteMsg::BatchTransfer { transfers } => {
+            execute_batch_transfer(deps, env, info, transfers)
+        }
+        ExecuteMsg::CreateSplit { name, recipients } => {
+            execute_create_split(deps, info, name, recipients)
+        }
+        ExecuteMsg::UpdateSplit {
+            split_id,
+            name,
+            recipients,
+        } => execute_update_split(deps, info, split_id, name, recipients),
+        ExecuteMsg::LockSplit { split_id } => execute_lock_split(deps, info, split_id),
+        ExecuteMsg::PayIntoSplit { split_id } => execute_pay_into_split(deps, env, info, split_id),
+        ExecuteMsg::InternalTransfer { to, amount } => {
+            execute_internal_transfer(deps, env, info, to, amount)
+        }
+        ExecuteMsg::InternalSplit {
+            amount,
+            recipient_1,
+            recipient_2,
+        } => execute_internal_split(deps, env, info, amount, recipient_1, recipient_2),
+        ExecuteMsg::IncreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => execute_increase_allowance(deps, env, info, spender, amount, expires),
+        ExecuteMsg::DecreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => execute_decrease_allowance(deps, env, info, spender, amount, expires),
+        ExecuteMsg::WithdrawFrom {
+            owner,
+            amount,
+            recipient,
+        } => execute_withdraw_from(deps, env, info, owner, amount, recipient),
+        ExecuteMsg::SetForwarding { preference } => {
+            execute_set_forwarding(deps, info, preference)
+        }
+        ExecuteMsg::Claim { transfer_id } => execute_claim(deps, env, info, transfer_id),
+        ExecuteMsg::Reclaim { transfer_id } => execute_reclaim(deps, env, info, transfer_id),
+        ExecuteMsg::SetAcceptance { window } => execute_set_acceptance(deps, info, window),
+        ExecuteMsg::Accept { transfer_id } => execute_accept(deps, env, info, transfer_id, true),
+        ExecuteMsg::Reject { transfer_id } => execute_accept(deps, env, info, transfer_id, false),
+        ExecuteMsg::CancelTransfer { transfer_id } => {
+            execute_cancel_transfer(deps, env, info, transfer_id)
+        }
+        ExecuteMsg::CreateStream {
+            recipients,
+            rate_per_second,
+            start,
+            end,
+        } => execute_create_stream(deps, env, info, recipients, rate_per_second, start, end),
+        ExecuteMsg::TopUpStream { stream_id } => execute_top_up_stream(deps, env, info, stream_id),
+        ExecuteMsg::CancelStream { stream_id } => execute_cancel_stream(deps, env, info, stream_id),
+        ExecuteMsg::CreateSchedule {
+            amount,
+            recipient_1,
+            recipient_2,
+            interval,
+            count,
+        } => execute_create_schedule(deps, env, info, amount, [recipient_1, recipient_2], interval, count),
+        ExecuteMsg::ExecuteDue { schedule_id } => execute_due(deps, env, info, schedule_id),
+        ExecuteMsg::CancelSchedule { schedule_id } => {
+            execute_cancel_schedule(deps, env, info, schedule_id)
+        }
+        ExecuteMsg::HashLockedTransfer {
+            transfer_amount,
+            recipient_1,
+            recipient_2,
+            hash,
+            expires,
+        } => execute_hash_locked_transfer(
+            deps,
+            env,
+            info,
+            transfer_amount,
+            [recipient_1, recipient_2],
+            hash,
+            expires,
+        ),
+        ExecuteMsg::RevealPreimage { lock_id, preimage } => {
+            execute_reveal_preimage(deps, env, lock_id, preimage)
+        }
+        ExecuteMsg::RefundHashLock { lock_id } => execute_refund_hash_lock(deps, env, lock_id),
+        ExecuteMsg::CreateEscrow {
+            recipient_1,
+            recipient_2,
+            arbiter,
+            joint_approval,
+        } => execute_create_escrow(
+            deps,
+            env,
+            info,
+            [recipient_1, recipient_2],
+            arbiter,
+            joint_approval,
+        ),
+        ExecuteMsg::ReleaseEscrow { escrow_id, amount } => {
+            execute_release_escrow(deps, env, info, escrow_id, amount)
+        }
+        ExecuteMsg::RefundEscrow { escrow_id } => execute_refund_escrow(deps, env, info, escrow_id),
+        ExecuteMsg::CreateInvoice {
+            amount,
+            denom,
+            co_recipient,
+            expires,
+        } => execute_create_invoice(deps, env, info, amount, denom, co_recipient, expires),
+        ExecuteMsg::PayInvoice { invoice_id } => execute_pay_invoice(deps, env, info, invoice_id),
+        ExecuteMsg::SetStaking { config } => execute_set_staking(deps, env, info, config),
+        ExecuteMsg::Rebalance {} => execute_rebalance(deps, env),
+        ExecuteMsg::ClaimWithdrawal {} => execute_claim_withdrawal(deps, env, info),
+        ExecuteMsg::HarvestRewards {} => execute_harvest_rewards(deps, env),
+        ExecuteMsg::ClaimRewards {} => execute_claim_rewards(deps, env, info),
+        ExecuteMsg::DelegateBalance { validator, amount } => {
+            execute_delegate_balance(deps, env, info, validator, amount)
+        }
+        ExecuteMsg::UndelegateBalance { validator, amount } => {
+            execute_undelegate_balance(deps, env, info, validator, amount)
+        }
+        ExecuteMsg::RedelegateBalance {
+            src_validator,
+            dst_validator,
+            amount,
+        } => execute_redelegate_balance(deps, env, info, src_validator, dst_validator, amount),
+        ExecuteMsg::WithdrawIbc {
+            channel_id,
+            remote_address,
+            amount,
+            timeout,
+        } => execute_withdraw_ibc(deps, env, info, channel_id, remote_address, amount, timeout),
+        ExecuteMsg::FundRemoteEscrow {
+            channel_id,
+            remote_sender,
+        } => execute_fund_remote_escrow(deps, info, channel_id, remote_sender),
+        ExecuteMsg::RefundRemoteEscrow {
+            channel_id,
+            remote_sender,
+        } => execute_refund_remote_escrow(deps, env, info, channel_id, remote_sender),
+        ExecuteMsg::SetIbcCounterparty { port_id } => {
+            execute_set_ibc_counterparty(deps, info, port_id)
+        }
+        ExecuteMsg::ReceiveSplit { channel_id, packet } => {
+            execute_receive_split(deps, env, info, channel_id, packet)
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        IBC_TRANSFER_REPLY_ID => reply_ibc_transfer(deps, msg.result),
+        HOOK_REPLY_ID => reply_hook(msg.result),
+        RECEIVE_SPLIT_REPLY_ID => reply_receive_split(msg),
+        id => Err(ContractError::UnknownReply { id }),
     }
 }
 
@@ -67,477 +744,6797 @@ pub fn execute_transfer(
     env: Env,
     info: MessageInfo,
     transfer_amount: Uint128,
-    recipient_1: String,
-    recipient_2: String,
+    recipients: [String; 2],
+    terms: TransferTerms,
+    msg: Option<Binary>,
+) -> Result<Response, ContractError> {
+    let fee = FEE.load(deps.storage)?;
+    let plan = plan_transfer(deps.as_ref(), transfer_amount, fee, Some(&info.funds), &recipients)?;
+    let mut forwards = vec![];
+    let event = apply_transfer(
+        deps.storage,
+        &env,
+        &info.sender,
+        &plan,
+        terms,
+        &mut forwards,
+    )?;
+
+    // Make the bank transfer
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let message = BankMsg::Send {
+        to_address: env.contract.address.to_string(),
+        amount: coins(plan.recipient_amount.u128() * 2u128, &coin_denom),
+    };
+
+    let legacy_attributes = event.legacy_attributes();
+    let mut res =
+        event_response(deps.storage, "transfer", event.into(), legacy_attributes)?.add_message(message);
+
+    // Return whatever was sent but not charged, such as the odd coin left over
+    if !plan.refund.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(plan.refund.u128(), &coin_denom),
+        });
+    }
+    if let Some(msg) = msg {
+        let hooks = recipient_hooks(deps.as_ref(), &info.sender, &plan, terms, &forwards, msg)?;
+        res = res.add_submessages(hooks);
+    }
+    add_forwards(deps, &env, res, forwards)
+}
+
+/// Calls `SplitReceived` on each recipient that is a contract and was
+/// credited by the transfer. Shares still waiting to be claimed, accepted or
+/// vested are not announced, nor are shares forwarded straight on. Unless
+/// `HookFailureMode::Revert` is set, a failing hook leaves the transfer in
+/// place.
+fn recipient_hooks(
+    deps: Deps,
+    sender: &Addr,
+    plan: &TransferPlan,
+    terms: TransferTerms,
+    forwards: &[ForwardEvent],
+    msg: Binary,
+) -> StdResult<Vec<SubMsg>> {
+    if terms.claim_deadline.is_some() || terms.vesting.is_some() {
+        return Ok(vec![]);
+    }
+    let mode = HOOK_FAILURE_MODE.may_load(deps.storage)?.unwrap_or_default();
+    let hook = to_json_binary(&ReceiverMsg::SplitReceived {
+        sender: sender.to_string(),
+        amount: plan.recipient_amount,
+        msg,
+    })?;
+    Ok(plan
+        .recipients
+        .iter()
+        .filter(|recipient| !ACCEPTANCE_WINDOWS.has(deps.storage, recipient))
+        .filter(|recipient| !forwards.iter().any(|forward| forward.recipient == **recipient))
+        .filter(|recipient| deps.querier.query_wasm_contract_info(recipient.as_str()).is_ok())
+        .map(|recipient| {
+            let execute = WasmMsg::Execute {
+                contract_addr: recipient.to_string(),
+                msg: hook.clone(),
+                funds: vec![],
+            };
+            match mode {
+                HookFailureMode::Ignore => SubMsg::reply_on_error(execute, HOOK_REPLY_ID),
+                HookFailureMode::Revert => SubMsg::new(execute),
+            }
+        })
+        .collect())
+}
+
+fn reply_hook(result: SubMsgResult) -> Result<Response, ContractError> {
+    let error = result.into_result().err().unwrap_or_default();
+    Ok(Response::new().add_attribute("hook_error", error))
+}
+
+/// Several two-recipient splits paid for with one set of funds. Each item's
+/// `amount` is what its recipients share; the attached funds must equal the
+/// sum of the amounts plus fees, charged per item or once for the batch.
+pub fn execute_batch_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfers: Vec<BatchTransferItem>,
 ) -> Result<Response, ContractError> {
-    
-    let sender_funds = info.funds;
-    let coin_denom: String = COIN_DENOM.load(deps.storage)?.to_string();
-    let fee = Uint128::new(FEE.load(deps.storage)?.u128());
+    if transfers.is_empty() {
+        return Err(ContractError::EmptyBatch {});
+    }
+    let fee = FEE.load(deps.storage)?;
+    let fee_mode = BATCH_FEE_MODE.may_load(deps.storage)?.unwrap_or_default();
+
+    // Plan every item before touching state. A per-batch fee is booked
+    // against the first item.
+    let mut plans = vec![];
+    let mut expected = Uint128::zero();
+    for (i, item) in transfers.into_iter().enumerate() {
+        let item_fee = match fee_mode {
+            BatchFeeMode::PerItem => fee,
+            BatchFeeMode::PerBatch if i == 0 => fee,
+            BatchFeeMode::PerBatch => Uint128::zero(),
+        };
+        let transfer_amount = item.amount.checked_add(item_fee)?;
+        let plan = plan_transfer(
+            deps.as_ref(),
+            transfer_amount,
+            item_fee,
+            None,
+            &[item.recipient_1, item.recipient_2],
+        )?;
+        expected = expected.checked_add(transfer_amount)?;
+        plans.push(plan);
+    }
+
+    let received = sent_amount(deps.storage, &info.funds)?;
+    if received != expected {
+        return Err(ContractError::IncorrectFunds { expected, received });
+    }
+
+    let mut res = Response::new().add_attribute("action", "batch_transfer");
+    let mut total_fee = Uint128::zero();
+    let mut refund = Uint128::zero();
+    let mut forwards = vec![];
+    for plan in &plans {
+        let event = apply_transfer(
+            deps.storage,
+            &env,
+            &info.sender,
+            plan,
+            TransferTerms::default(),
+            &mut forwards,
+        )?;
+        total_fee += plan.fee;
+        refund += plan.refund;
+        res = res.add_event(event.into());
+    }
+    let event = BatchTransferEvent {
+        sender: info.sender.clone(),
+        count: plans.len() as u64,
+        total_amount: expected,
+        total_fee,
+        refund,
+    };
+    res = res.add_event(event.into());
+
+    // Odd amounts leave a coin per item that could not be split evenly
+    if !refund.is_zero() {
+        let coin_denom = COIN_DENOM.load(deps.storage)?;
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(refund.u128(), &coin_denom),
+        });
+    }
+    add_forwards(deps, &env, res, forwards)
+}
+
+/// Optional conditions a transfer is made under.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TransferTerms {
+    pub claim_deadline: Option<Expiration>,
+    pub cancel_deadline: Option<Expiration>,
+    pub vesting: Option<Vesting>,
+}
+
+/// Credits the recipients and the owner for a planned transfer and records
+/// it in the transfer history.
+fn apply_transfer(
+    storage: &mut dyn Storage,
+    env: &Env,
+    sender: &Addr,
+    plan: &TransferPlan,
+    terms: TransferTerms,
+    forwards: &mut Vec<ForwardEvent>,
+) -> Result<SplitTransferEvent, ContractError> {
+    let TransferTerms {
+        claim_deadline,
+        cancel_deadline,
+        vesting,
+    } = terms;
+    if let Some(vesting) = vesting {
+        if claim_deadline.is_some() {
+            return Err(ContractError::ConflictingTerms {});
+        }
+        let cliff = vesting.cliff.unwrap_or(vesting.start);
+        if vesting.end <= vesting.start || cliff < vesting.start || cliff > vesting.end {
+            return Err(ContractError::InvalidVesting {});
+        }
+    }
+    let [recipient_1, recipient_2] = plan.recipients.clone();
+    let transfer_id = TRANSFER_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    TRANSFER_COUNT.save(storage, &transfer_id)?;
+
+    // Update the recipients' and owner's balances. With a claim deadline the
+    // recipients' shares wait as pending claims instead, vesting shares are
+    // released as they vest, and recipients that accept transfers themselves
+    // get a pending acceptance.
+    for recipient in [&recipient_1, &recipient_2] {
+        let window = ACCEPTANCE_WINDOWS.may_load(storage, recipient)?;
+        if let Some(schedule) = vesting {
+            // Vested releases land straight in the balance, which would skip
+            // both the acceptance and the forwarding
+            if window.is_some() || FORWARDING.has(storage, recipient) {
+                return Err(ContractError::CannotVest {});
+            }
+            let amount = plan.recipient_amount;
+            add_vesting(storage, &env.block, transfer_id, recipient, amount, schedule)?;
+            reserve_funds(storage, amount)?;
+            continue;
+        }
+        match (claim_deadline, window) {
+            (Some(expires), _) => {
+                if expires.is_expired(&env.block) {
+                    return Err(ContractError::InvalidExpiration {});
+                }
+                let amount = plan.recipient_amount;
+                add_pending(claims(), storage, transfer_id, sender, recipient, amount, expires)?;
+                reserve_funds(storage, amount)?;
+            }
+            (None, Some(window)) => {
+                let expires = window.after(&env.block);
+                let amount = plan.recipient_amount;
+                add_pending(acceptances(), storage, transfer_id, sender, recipient, amount, expires)?;
+                reserve_funds(storage, amount)?;
+            }
+            (None, None) => {
+                let amount = plan.recipient_amount;
+                let kept = credit_recipient(storage, &env.block, recipient, amount, forwards)?;
+                if let (true, Some(expires)) = (kept, cancel_deadline) {
+                    let credit = CancellableCredit {
+                        credited: amount,
+                        fee: Uint128::zero(),
+                        expires,
+                    };
+                    note_cancellable(storage, &env.block, recipient, transfer_id, credit)?;
+                }
+            }
+        }
+    }
+    let owner = OWNER.load(storage)?;
+    add_balance(storage, &owner, plan.fee, env.block.height)?;
+    if let Some(expires) = cancel_deadline.filter(|_| !plan.fee.is_zero()) {
+        let credit = CancellableCredit {
+            credited: Uint128::zero(),
+            fee: plan.fee,
+            expires,
+        };
+        note_cancellable(storage, &env.block, &owner, transfer_id, credit)?;
+    }
+
+    // Record the transfer so it can be queried later
+    let record = TransferRecord {
+        id: transfer_id,
+        sender: sender.clone(),
+        recipient_1: recipient_1.clone(),
+        recipient_2: recipient_2.clone(),
+        recipient_amount: plan.recipient_amount,
+        fee: plan.fee,
+        sender_charged: plan.sender_charged,
+        block_height: env.block.height,
+        block_time: env.block.time,
+        claim_deadline,
+        cancel_deadline,
+        cancelled: false,
+        vesting,
+    };
+    transfers().save(storage, transfer_id, &record)?;
+
+    Ok(SplitTransferEvent {
+        transfer_id,
+        sender: sender.clone(),
+        recipient_1,
+        recipient_2,
+        owner,
+        recipient_amount: plan.recipient_amount,
+        fee: plan.fee,
+        sender_charged: plan.sender_charged,
+        refund: plan.refund,
+        claim_deadline,
+        vesting,
+    })
+}
+
+fn add_vesting(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    transfer_id: u64,
+    recipient: &Addr,
+    amount: Uint128,
+    schedule: Vesting,
+) -> Result<(), ContractError> {
+    let key = (recipient, transfer_id);
+    if !VESTING.has(storage, key) {
+        // Finished grants are dropped as they are released, so they don't count
+        release_vested(storage, block, recipient)?;
+        let grants = VESTING
+            .prefix(recipient)
+            .keys(storage, None, None, Order::Ascending)
+            .take(MAX_VESTING_GRANTS)
+            .count();
+        if grants == MAX_VESTING_GRANTS {
+            return Err(ContractError::TooManyGrants {});
+        }
+    }
+    VESTING.update(storage, key, |grant| -> StdResult<_> {
+        Ok(match grant {
+            Some(mut grant) => {
+                grant.total += amount;
+                grant
+            }
+            None => VestingGrant {
+                transfer_id,
+                total: amount,
+                released: Uint128::zero(),
+                schedule,
+            },
+        })
+    })?;
+    Ok(())
+}
+
+/// How much of a grant has vested at `now`.
+fn vested_amount(grant: &VestingGrant, now: Timestamp) -> Uint128 {
+    let Vesting { start, end, cliff } = grant.schedule;
+    if now <= start || now < cliff.unwrap_or(start) {
+        Uint128::zero()
+    } else if now >= end {
+        grant.total
+    } else {
+        grant
+            .total
+            .multiply_ratio(now.seconds() - start.seconds(), end.seconds() - start.seconds())
+    }
+}
+
+/// Moves whatever has vested or streamed since the last release into
+/// `recipient`'s balance, ahead of spending from it.
+fn release_accrued(storage: &mut dyn Storage, block: &BlockInfo, recipient: &Addr) -> StdResult<()> {
+    release_vested(storage, block, recipient)?;
+    let (streams, released) = stream_accruals(storage, recipient, block.time)?;
+    for (stream_id, streamed, ended) in streams {
+        // An ended stream has nothing left to release
+        if ended {
+            STREAMED.remove(storage, (recipient, stream_id));
+        } else {
+            STREAMED.save(storage, (recipient, stream_id), &streamed)?;
+        }
+    }
+    unreserve_funds(storage, released)?;
+    add_balance(storage, recipient, released, block.height)
+}
+
+/// What has vested across `recipient`'s grants but is not in their balance yet.
+fn vesting_accruals(storage: &dyn Storage, recipient: &Addr, now: Timestamp) -> StdResult<Uint128> {
+    let mut unreleased = Uint128::zero();
+    for item in VESTING.prefix(recipient).range(storage, None, None, Order::Ascending) {
+        let (_, grant) = item?;
+        unreleased += vested_amount(&grant, now).saturating_sub(grant.released);
+    }
+    Ok(unreleased)
+}
+
+fn release_vested(storage: &mut dyn Storage, block: &BlockInfo, recipient: &Addr) -> StdResult<Uint128> {
+    let grants = VESTING
+        .prefix(recipient)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, grant)| grant))
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut released = Uint128::zero();
+    for mut grant in grants {
+        let vested = vested_amount(&grant, block.time);
+        if vested > grant.released {
+            let amount = vested - grant.released;
+            released += amount;
+            grant.released = vested;
+            // A fully released grant has nothing left to track
+            if grant.released == grant.total {
+                VESTING.remove(storage, (recipient, grant.transfer_id));
+            } else {
+                VESTING.save(storage, (recipient, grant.transfer_id), &grant)?;
+            }
+            if let Some(expires) = transfers().load(storage, grant.transfer_id)?.cancel_deadline {
+                let credit = CancellableCredit {
+                    credited: amount,
+                    fee: Uint128::zero(),
+                    expires,
+                };
+                note_cancellable(storage, block, recipient, grant.transfer_id, credit)?;
+            }
+        }
+    }
+    unreserve_funds(storage, released)?;
+    add_balance(storage, recipient, released, block.height)?;
+    Ok(released)
+}
+
+/// Remembers a credit from a cancellable transfer, so `CancelTransfer` knows
+/// how much it may take back from `address`.
+fn note_cancellable(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    address: &Addr,
+    transfer_id: u64,
+    credit: CancellableCredit,
+) -> StdResult<()> {
+    prune_cancellable(storage, block, address)?;
+    CANCELLABLE_CREDITS.update(storage, (address, transfer_id), |noted| -> StdResult<_> {
+        Ok(match noted {
+            Some(mut noted) => {
+                noted.credited += credit.credited;
+                noted.fee += credit.fee;
+                noted
+            }
+            None => credit,
+        })
+    })?;
+    Ok(())
+}
+
+/// Drops `address`'s cancellable credits whose cancel window has closed.
+fn prune_cancellable(storage: &mut dyn Storage, block: &BlockInfo, address: &Addr) -> StdResult<()> {
+    let expired = CANCELLABLE_CREDITS
+        .prefix(address)
+        .range(storage, None, None, Order::Ascending)
+        .filter(|item| !matches!(item, Ok((_, credit)) if !credit.expires.is_expired(block)))
+        .map(|item| item.map(|(transfer_id, _)| transfer_id))
+        .collect::<StdResult<Vec<_>>>()?;
+    for transfer_id in expired {
+        CANCELLABLE_CREDITS.remove(storage, (address, transfer_id));
+    }
+    Ok(())
+}
+
+/// Debits `address`'s balance for something they chose to do with it, after
+/// releasing whatever has vested or streamed to them. The rest of the balance
+/// is spent before any cancellable credit, and then the oldest credits first,
+/// so a later `CancelTransfer` cannot take back more than is left of its own
+/// credit.
+fn spend_balance(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    address: &Addr,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    release_accrued(storage, block, address)?;
+    let balance = BALANCE.may_load(storage, address)?.unwrap_or_default();
+    sub_balance(storage, address, amount, block.height)?;
+    prune_cancellable(storage, block, address)?;
+    let credits = CANCELLABLE_CREDITS
+        .prefix(address)
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let cancellable: Uint128 = credits.iter().map(|(_, credit)| credit.credited + credit.fee).sum();
+    let mut unspent = amount.saturating_sub(balance.saturating_sub(cancellable));
+    for (transfer_id, mut credit) in credits {
+        if unspent.is_zero() {
+            break;
+        }
+        let spent = credit.credited.min(unspent);
+        credit.credited -= spent;
+        unspent -= spent;
+        let spent = credit.fee.min(unspent);
+        credit.fee -= spent;
+        unspent -= spent;
+        if credit.credited.is_zero() && credit.fee.is_zero() {
+            CANCELLABLE_CREDITS.remove(storage, (address, transfer_id));
+        } else {
+            CANCELLABLE_CREDITS.save(storage, (address, transfer_id), &credit)?;
+        }
+    }
+    Ok(())
+}
+
+fn add_pending(
+    pending: PendingMap,
+    storage: &mut dyn Storage,
+    transfer_id: u64,
+    sender: &Addr,
+    recipient: &Addr,
+    amount: Uint128,
+    expires: Expiration,
+) -> StdResult<()> {
+    // A recipient named twice in one transfer gets a single, larger entry
+    pending.update(storage, (transfer_id, recipient), |claim| -> StdResult<_> {
+        Ok(match claim {
+            Some(mut claim) => {
+                claim.amount += amount;
+                claim
+            }
+            None => PendingClaim {
+                transfer_id,
+                sender: sender.clone(),
+                recipient: recipient.clone(),
+                amount,
+                expires,
+            },
+        })
+    })?;
+    Ok(())
+}
+
+/// How a transfer is divided up. Shared by `Transfer` and `SimulateTransfer`
+/// so a simulation always matches what the real call would do.
+#[derive(Debug, PartialEq)]
+pub struct TransferPlan {
+    pub recipients: [Addr; 2],
+    pub fee: Uint128,
+    /// Amount credited to each recipient.
+    pub recipient_amount: Uint128,
+    pub sender_charged: Uint128,
+    /// Funds sent but not charged, returned to the sender.
+    pub refund: Uint128,
+}
+
+/// Splits `transfer_amount` evenly between `recipients` after the fee. When
+/// `funds` is `None` the sender is assumed to attach exactly `transfer_amount`.
+pub fn plan_transfer(
+    deps: Deps,
+    transfer_amount: Uint128,
+    fee: Uint128,
+    funds: Option<&[Coin]>,
+    recipients: &[String; 2],
+) -> Result<TransferPlan, ContractError> {
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
 
     if fee.gt(&transfer_amount){
         return Err(ContractError::SentLessThanFee {});
     }
 
-    // The recipients get floor(trannsfer_amout - fee /2) sei. 
+    // The recipients get floor(transfer_amount - fee / n) sei.
     // The owner gets the fee.
     // The remainder is not taken from the user.
-    // Note that floor(trannsfer_amout - fee /2) must be greater than 1 (otherwise recipients cant get evenly paid).
-    let transfer_amount_minus_fee = transfer_amount.checked_sub(fee).unwrap();
-    let recipient_amt = transfer_amount_minus_fee
-        .checked_div_floor((2u128, 1u128))
-        .unwrap();
-
+    // Note that floor(transfer_amount - fee / n) must be at least 1 (otherwise recipients cant get evenly paid).
+    let count = Uint128::from(recipients.len() as u128);
+    let recipient_amt = (transfer_amount - fee) / count;
 
     // Make sure the sender actually has enough of the right coins to transfer
-    if coin_denom != sender_funds[0].denom {
-        return Err(ContractError::SentIncorrectCoin {});
-    }
+    let sent = match funds {
+        Some(funds) => {
+            let coin = funds.first().ok_or(ContractError::NotEnoughCoin {})?;
+            if coin_denom != coin.denom {
+                return Err(ContractError::SentIncorrectCoin {});
+            }
+            coin.amount
+        }
+        None => transfer_amount,
+    };
 
-    if transfer_amount > sender_funds[0].amount {
+    if transfer_amount > sent {
         return Err(ContractError::NotEnoughCoin {});
     }
 
-    if recipient_amt==Uint128::new(0){
+    if recipient_amt.is_zero() {
         return Err(ContractError::RecipientPaidZeroOrOneCoin {});
     }
 
-    
+    let recipients = [
+        deps.api.addr_validate(&recipients[0])?,
+        deps.api.addr_validate(&recipients[1])?,
+    ];
 
-    // Get recipients
-    let recipient_1 = deps.api.addr_validate(recipient_1.as_str())?;
-    let recipient_2 = deps.api.addr_validate(recipient_2.as_str())?;
+    let sender_charged = fee + recipient_amt.checked_mul(count)?;
+    Ok(TransferPlan {
+        recipients,
+        fee,
+        recipient_amount: recipient_amt,
+        sender_charged,
+        refund: sent - sender_charged,
+    })
+}
 
-    // Update recipient_1s balance
-    BALANCE.update(
-        deps.storage,
-        &recipient_1,
-        |balance: Option<Uint128>| -> StdResult<_> {
-            Ok(balance.unwrap_or_default() + recipient_amt)
-        },
-    )?;
-    // Update recipient_2s balance
-    BALANCE.update(
-        deps.storage,
-        &recipient_2,
-        |balance: Option<Uint128>| -> StdResult<_> {
-            Ok(balance.unwrap_or_default() + recipient_amt)
-        },
-    )?;
-    // Update Owners balance
-    let owner = OWNER.load(deps.storage)?;
-    BALANCE.update(
-        deps.storage,
-        &owner,
-        |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + fee) },
-    )?;
+pub fn execute_create_split(
+    deps: DepsMut,
+    info: MessageInfo,
+    name: String,
+    recipients: Vec<SplitRecipient>,
+) -> Result<Response, ContractError> {
+    let recipients = validate_split_recipients(deps.as_ref(), recipients)?;
+    check_split_tree(deps.storage, &recipients, None, 0)?;
+    let split_id = SPLIT_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    SPLIT_COUNT.save(deps.storage, &split_id)?;
+    let split = Split {
+        id: split_id,
+        name,
+        owner: info.sender,
+        recipients,
+        locked: false,
+    };
+    splits().save(deps.storage, split_id, &split)?;
 
-    // Make the bank transfer
-    let coin_denom = COIN_DENOM.load(deps.storage)?;
-    let message = BankMsg::Send {
-        to_address: env.contract.address.to_string(),
-        amount: coins(recipient_amt.u128() * (2 as u128), &coin_denom),
+    let event = SplitCreatedEvent {
+        split_id,
+        owner: split.owner,
+        name: split.name,
+        recipients: split.recipients.len() as u64,
     };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "create_split", event.into(), legacy_attributes)?)
+}
 
-    let sender_charged = fee.checked_add(recipient_amt.checked_mul(Uint128::new(2)).unwrap()).unwrap();
+pub fn execute_update_split(
+    deps: DepsMut,
+    info: MessageInfo,
+    split_id: u64,
+    name: Option<String>,
+    recipients: Vec<SplitRecipient>,
+) -> Result<Response, ContractError> {
+    let mut split = load_owned_split(deps.as_ref(), &info, split_id)?;
+    if split.locked {
+        return Err(ContractError::SplitLocked {});
+    }
+    split.recipients = validate_split_recipients(deps.as_ref(), recipients)?;
+    check_split_tree(deps.storage, &split.recipients, Some(split_id), 0)?;
+    if let Some(name) = name {
+        split.name = name;
+    }
+    splits().save(deps.storage, split_id, &split)?;
 
-    Ok(Response::new().add_message(message).add_attributes(vec![
-        ("action", "transfer"),
-        ("recipient_1", recipient_1.as_str()),
-        ("recipient_2", recipient_2.as_str()),
-        ("owner", owner.as_str()),
-        ("recipient_1_recieved", &recipient_amt.to_string()),
-        ("recipient_2_recieved", &recipient_amt.to_string()),
-        ("owner_recieved", &fee.to_string()),
-        ("sender_charged", &sender_charged.to_string()),
-    ]))
+    let event = SplitUpdatedEvent {
+        split_id,
+        name: split.name,
+        recipients: split.recipients.len() as u64,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "update_split", event.into(), legacy_attributes)?)
 }
 
-pub fn execute_withdraw(
+/// Freezes a split's recipients for good, so payers can rely on them.
+pub fn execute_lock_split(
     deps: DepsMut,
-    _env: Env,
     info: MessageInfo,
-    amount: Uint128,
+    split_id: u64,
 ) -> Result<Response, ContractError> {
-    // Check that the sender has enough to withdraw
-    let balance = BALANCE
-        .may_load(deps.storage, &info.sender)?
-        .unwrap_or_default();
+    let mut split = load_owned_split(deps.as_ref(), &info, split_id)?;
+    split.locked = true;
+    splits().save(deps.storage, split_id, &split)?;
+
+    let event = SplitLockedEvent { split_id };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "lock_split", event.into(), legacy_attributes)?)
+}
 
-    let transfer_check = balance.lt(&amount);
-    if transfer_check {
-        return Err(ContractError::NotEnoughBalance {});
+/// Divides the attached funds, less the fee, between a split's recipients in
+/// proportion to their shares, passing nested splits' portions down the tree.
+/// Rounding dust is returned to the sender.
+pub fn execute_pay_into_split(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    split_id: u64,
+) -> Result<Response, ContractError> {
+    let split = splits().load(deps.storage, split_id)?;
+    let amount = sent_amount(deps.storage, &info.funds)?;
+    let fee = FEE.load(deps.storage)?;
+    if fee > amount {
+        return Err(ContractError::SentLessThanFee {});
     }
 
-    // Update the senders balance
-    BALANCE.update(
+    let mut nested_events = vec![];
+    let mut forwards = vec![];
+    let (credits, dust) = distribute_split(
         deps.storage,
-        &info.sender,
-        |balance: Option<Uint128>| -> StdResult<_> {
-            Ok(balance.unwrap_or_default().checked_sub(amount).unwrap())
-        },
+        &env.block,
+        &split,
+        amount - fee,
+        0,
+        &mut nested_events,
+        &mut forwards,
     )?;
+    let owner = OWNER.load(deps.storage)?;
+    add_balance(deps.storage, &owner, fee, env.block.height)?;
 
-    // Make the bank transfer
-    let coin_denom = COIN_DENOM.load(deps.storage)?;
-    let message = BankMsg::Send {
-        to_address: info.sender.to_string(),
-        amount: coins(amount.u128(), &coin_denom),
+    let credited: Uint128 = credits.iter().map(|(_, credit)| *credit).sum();
+    let refund = amount - fee - credited + dust;
+    let event = SplitPaymentEvent {
+        split_id,
+        sender: info.sender.clone(),
+        fee,
+        refund,
+        credits,
     };
-
-    Ok(Response::new().add_message(message).add_attributes(vec![
-        ("action", "withdraw"),
-        ("sender", info.sender.as_str()),
-        ("withdraw_amount", &amount.to_string()),
-    ]))
+    let mut res = Response::new()
+        .add_attribute("action", "pay_into_split")
+        .add_event(event.into())
+        .add_events(nested_events);
+    if !refund.is_zero() {
+        let coin_denom = COIN_DENOM.load(deps.storage)?;
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(refund.u128(), &coin_denom),
+        });
+    }
+    add_forwards(deps, &env, res, forwards)
 }
 
-#[cfg(test)]
-mod tests {
+/// Credits `amount` across `split`, descending into nested splits. Returns
+/// the split's own credits and the rounding dust from any nested splits, and
+/// pushes a `split_distribution` event for every nested split, parents first.
+fn distribute_split(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    split: &Split,
+    amount: Uint128,
+    depth: u32,
+    events: &mut Vec<Event>,
+    forwards: &mut Vec<ForwardEvent>,
+) -> Result<(Vec<(SplitPayee, Uint128)>, Uint128), ContractError> {
+    let credits = divide_by_shares(amount, &split.recipients)?;
+    let mut dust = Uint128::zero();
+    for (payee, credit) in &credits {
+        match payee {
+            SplitPayee::Address(address) => {
+                credit_recipient(storage, block, address, *credit, forwards)?;
+            }
+            SplitPayee::Split(child_id) => {
+                if depth + 1 > MAX_SPLIT_DEPTH {
+                    return Err(ContractError::SplitTooDeep { max: MAX_SPLIT_DEPTH });
+                }
+                let child = splits().load(storage, *child_id)?;
+                let position = events.len();
+                let (child_credits, child_dust) =
+                    distribute_split(storage, block, &child, *credit, depth + 1, events, forwards)?;
+                let child_credited: Uint128 = child_credits.iter().map(|(_, c)| *c).sum();
+                dust += *credit - child_credited + child_dust;
+                let event = SplitDistributionEvent {
+                    split_id: child.id,
+                    parent_split_id: split.id,
+                    depth: depth + 1,
+                    amount: *credit,
+                    credits: child_credits,
+                };
+                events.insert(position, event.into());
+            }
+        }
+    }
+    Ok((credits, dust))
+}
 
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, from_binary};
+/// Walks the nested splits below `recipients`, failing if they reach
+/// `split_id` (which would make a cycle) or go deeper than `MAX_SPLIT_DEPTH`.
+fn check_split_tree(
+    storage: &dyn Storage,
+    recipients: &[SplitShare],
+    split_id: Option<u64>,
+    depth: u32,
+) -> Result<(), ContractError> {
+    for recipient in recipients {
+        if let SplitPayee::Split(child_id) = recipient.payee {
+            if Some(child_id) == split_id {
+                return Err(ContractError::SplitCycle {});
+            }
+            if depth + 1 > MAX_SPLIT_DEPTH {
+                return Err(ContractError::SplitTooDeep { max: MAX_SPLIT_DEPTH });
+            }
+            let child = splits().load(storage, child_id)?;
+            check_split_tree(storage, &child.recipients, split_id, depth + 1)?;
+        }
+    }
+    Ok(())
+}
 
-    use super::*;
+fn load_owned_split(deps: Deps, info: &MessageInfo, split_id: u64) -> Result<Split, ContractError> {
+    let split = splits().load(deps.storage, split_id)?;
+    if split.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(split)
+}
 
-    #[test]
-    fn test_instantiate() {
-        // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
-        let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+fn validate_split_recipients(
+    deps: Deps,
+    recipients: Vec<SplitRecipient>,
+) -> Result<Vec<SplitShare>, ContractError> {
+    if recipients.is_empty() {
+        return Err(ContractError::NoRecipients {});
     }
+    recipients
+        .iter()
+        .try_fold(0u64, |total, recipient| total.checked_add(recipient.shares))
+        .ok_or(ContractError::SharesOverflow {})?;
+    recipients
+        .into_iter()
+        .map(|recipient| {
+            if recipient.shares == 0 {
+                return Err(ContractError::ZeroShares {});
+            }
+            let payee = match recipient.payee {
+                Payee::Address(address) => SplitPayee::Address(deps.api.addr_validate(&address)?),
+                Payee::Split(split_id) => SplitPayee::Split(split_id),
+            };
+            Ok(SplitShare {
+                payee,
+                shares: recipient.shares,
+            })
+        })
+        .collect()
+}
 
-    #[test]
-    fn test_query_owner_address() {
-        // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
-        let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+/// Each recipient gets floor(amount * shares / total_shares). Every recipient
+/// must receive something, as with an even `Transfer`.
+fn divide_by_shares(
+    amount: Uint128,
+    recipients: &[SplitShare],
+) -> Result<Vec<(SplitPayee, Uint128)>, ContractError> {
+    let total_shares = recipients
+        .iter()
+        .try_fold(0u64, |total, recipient| total.checked_add(recipient.shares))
+        .ok_or(ContractError::SharesOverflow {})?;
+    recipients
+        .iter()
+        .map(|recipient| {
+            let credit = amount.multiply_ratio(recipient.shares, total_shares);
+            if credit.is_zero() {
+                return Err(ContractError::RecipientPaidZeroOrOneCoin {});
+            }
+            Ok((recipient.payee.clone(), credit))
+        })
+        .collect()
+}
+
+/// The amount of the contract's coin in `funds`, which must hold nothing else.
+fn sent_amount(storage: &dyn Storage, funds: &[Coin]) -> Result<Uint128, ContractError> {
+    let coin_denom = COIN_DENOM.load(storage)?;
+    match funds {
+        [coin] if coin.denom == coin_denom => Ok(coin.amount),
+        [] => Ok(Uint128::zero()),
+        _ => Err(ContractError::SentIncorrectCoin {}),
+    }
+}
+
+/// Moves credit from the sender's balance to `to` without touching the bank.
+pub fn execute_internal_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    to: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let to = deps.api.addr_validate(&to)?;
+    let fee = FEE.load(deps.storage)?;
+    let (debited, credited, fee) = match internal_fee_mode(deps.storage)? {
+        InternalFeeMode::Free => (amount, amount, Uint128::zero()),
+        InternalFeeMode::Deducted => {
+            if fee > amount {
+                return Err(ContractError::SentLessThanFee {});
+            }
+            (amount, amount - fee, fee)
+        }
+        InternalFeeMode::OnTop => (amount.checked_add(fee)?, amount, fee),
+    };
+    if credited.is_zero() {
+        return Err(ContractError::RecipientPaidZeroOrOneCoin {});
+    }
+
+    spend_balance(deps.storage, &env.block, &info.sender, debited)?;
+    add_balance(deps.storage, &to, credited, env.block.height)?;
+    let owner = OWNER.load(deps.storage)?;
+    add_balance(deps.storage, &owner, fee, env.block.height)?;
+
+    let event = InternalTransferEvent {
+        sender: info.sender,
+        recipient: to,
+        owner,
+        debited,
+        credited,
+        fee,
+    };
+    Ok(Response::new()
+        .add_attribute("action", "internal_transfer")
+        .add_event(event.into()))
+}
+
+/// A `Transfer` paid for out of the sender's balance instead of attached
+/// funds. Only what is charged is debited, so an odd coin stays put.
+pub fn execute_internal_split(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+    recipient_1: String,
+    recipient_2: String,
+) -> Result<Response, ContractError> {
+    let fee = FEE.load(deps.storage)?;
+    let (transfer_amount, fee) = match internal_fee_mode(deps.storage)? {
+        InternalFeeMode::Free => (amount, Uint128::zero()),
+        InternalFeeMode::Deducted => (amount, fee),
+        InternalFeeMode::OnTop => (amount.checked_add(fee)?, fee),
+    };
+    let plan = plan_transfer(
+        deps.as_ref(),
+        transfer_amount,
+        fee,
+        None,
+        &[recipient_1, recipient_2],
+    )?;
+    spend_balance(deps.storage, &env.block, &info.sender, plan.sender_charged)?;
+    let mut forwards = vec![];
+    let event = apply_transfer(
+        deps.storage,
+        &env,
+        &info.sender,
+        &plan,
+        TransferTerms::default(),
+        &mut forwards,
+    )?;
+
+    let res = Response::new()
+        .add_attribute("action", "internal_split")
+        .add_event(event.into());
+    add_forwards(deps, &env, res, forwards)
+}
+
+fn internal_fee_mode(storage: &dyn Storage) -> StdResult<InternalFeeMode> {
+    Ok(INTERNAL_FEE_MODE.may_load(storage)?.unwrap_or_default())
+}
+
+pub fn execute_withdraw(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    // Check that the sender has enough to withdraw and update their balance
+    spend_balance(deps.storage, &env.block, &info.sender, amount)?;
+
+    // Make the bank transfer, or queue it while staked funds unbond
+    let (messages, queued) = pay_out(deps.branch(), &env, &info.sender, amount)?;
+
+    let event = WithdrawEvent {
+        sender: info.sender,
+        amount,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let mut res = event_response(deps.storage, "withdraw", event.into(), legacy_attributes)?;
+    if queued {
+        res = res.add_attribute("queued", "true");
+    }
+    Ok(res.add_messages(messages))
+}
+
+pub fn execute_increase_allowance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    amount: Uint128,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let spender = deps.api.addr_validate(&spender)?;
+    if spender == info.sender {
+        return Err(ContractError::CannotSetOwnAccount {});
+    }
+
+    ALLOWANCES.update(
+        deps.storage,
+        (&info.sender, &spender),
+        |allowance| -> Result<_, ContractError> {
+            let mut allowance = allowance.unwrap_or_default();
+            if let Some(expires) = expires {
+                if expires.is_expired(&env.block) {
+                    return Err(ContractError::InvalidExpiration {});
+                }
+                allowance.expires = expires;
+            }
+            allowance.allowance = allowance.allowance.checked_add(amount)?;
+            Ok(allowance)
+        },
+    )?;
+
+    let event = AllowanceEvent {
+        owner: info.sender,
+        spender,
+        amount,
+        increased: true,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "increase_allowance", event.into(), legacy_attributes)?)
+}
+
+pub fn execute_decrease_allowance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    amount: Uint128,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let spender = deps.api.addr_validate(&spender)?;
+    if spender == info.sender {
+        return Err(ContractError::CannotSetOwnAccount {});
+    }
+
+    // Decreasing to zero or below removes the allowance entirely
+    let key = (&info.sender, &spender);
+    let mut allowance = ALLOWANCES.may_load(deps.storage, key)?.unwrap_or_default();
+    if amount < allowance.allowance {
+        allowance.allowance -= amount;
+        if let Some(expires) = expires {
+            if expires.is_expired(&env.block) {
+                return Err(ContractError::InvalidExpiration {});
+            }
+            allowance.expires = expires;
+        }
+        ALLOWANCES.save(deps.storage, key, &allowance)?;
+    } else {
+        ALLOWANCES.remove(deps.storage, key);
+    }
+
+    let event = AllowanceEvent {
+        owner: info.sender,
+        spender,
+        amount,
+        increased: false,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "decrease_allowance", event.into(), legacy_attributes)?)
+}
+
+/// Withdraws from `owner`'s balance to `recipient`, spending the sender's
+/// allowance.
+pub fn execute_withdraw_from(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    amount: Uint128,
+    recipient: String,
+) -> Result<Response, ContractError> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+
+    ALLOWANCES.update(
+        deps.storage,
+        (&owner, &info.sender),
+        |allowance| -> Result<_, ContractError> {
+            let mut allowance = allowance.ok_or(ContractError::NoAllowance {})?;
+            if allowance.expires.is_expired(&env.block) {
+                return Err(ContractError::Expired {});
+            }
+            allowance.allowance = allowance
+                .allowance
+                .checked_sub(amount)
+                .map_err(|_| ContractError::NoAllowance {})?;
+            Ok(allowance)
+        },
+    )?;
+    spend_balance(deps.storage, &env.block, &owner, amount)?;
+
+    let (messages, queued) = pay_out(deps.branch(), &env, &recipient, amount)?;
+    let event = WithdrawFromEvent {
+        owner,
+        spender: info.sender,
+        recipient,
+        amount,
+    };
+    let mut res = Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "withdraw_from")
+        .add_event(event.into());
+    if queued {
+        res = res.add_attribute("queued", "true");
+    }
+    Ok(res)
+}
+
+/// Sets or, with `None`, clears how the sender's incoming credits are handled.
+pub fn execute_set_forwarding(
+    deps: DepsMut,
+    info: MessageInfo,
+    preference: Option<ForwardPreference>,
+) -> Result<Response, ContractError> {
+    let forwarding = match preference {
+        None => {
+            FORWARDING.remove(deps.storage, &info.sender);
+            let event = ForwardingEvent {
+                address: info.sender,
+                forwarding: None,
+            };
+            let legacy_attributes = event.legacy_attributes();
+            return Ok(event_response(
+                deps.storage,
+                "clear_forwarding",
+                event.into(),
+                legacy_attributes,
+            )?);
+        }
+        Some(ForwardPreference::Withdraw {}) => Forwarding::Withdraw,
+        Some(ForwardPreference::ForwardTo { address }) => {
+            Forwarding::ForwardTo(deps.api.addr_validate(&address)?)
+        }
+        Some(ForwardPreference::Threshold { amount }) => Forwarding::Threshold(amount),
+    };
+    FORWARDING.save(deps.storage, &info.sender, &forwarding)?;
+
+    let event = ForwardingEvent {
+        address: info.sender,
+        forwarding: Some(forwarding),
+    };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "set_forwarding", event.into(), legacy_attributes)?)
+}
+
+/// Credits `amount` to `recipient`, or queues it to be paid out straight
+/// away if they have asked for their credits to be forwarded. Returns whether
+/// the credit stayed in their balance.
+///
+/// Only recipients' shares come through here. Fees are never forwarded: they
+/// always go to the owner's balance, where a cancel can still take them back.
+fn credit_recipient(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    recipient: &Addr,
+    amount: Uint128,
+    forwards: &mut Vec<ForwardEvent>,
+) -> Result<bool, ContractError> {
+    let to = match FORWARDING.may_load(storage, recipient)? {
+        None => {
+            add_balance(storage, recipient, amount, block.height)?;
+            return Ok(true);
+        }
+        Some(Forwarding::Withdraw) => recipient.clone(),
+        Some(Forwarding::ForwardTo(to)) => to,
+        Some(Forwarding::Threshold(threshold)) => {
+            add_balance(storage, recipient, amount, block.height)?;
+            release_accrued(storage, block, recipient)?;
+            let balance = BALANCE.load(storage, recipient)?;
+            if balance < threshold {
+                return Ok(true);
+            }
+            spend_balance(storage, block, recipient, balance)?;
+            forwards.push(ForwardEvent {
+                recipient: recipient.clone(),
+                to: recipient.clone(),
+                amount: balance,
+            });
+            return Ok(false);
+        }
+    };
+    forwards.push(ForwardEvent {
+        recipient: recipient.clone(),
+        to,
+        amount,
+    });
+    Ok(false)
+}
+
+/// Pays out forwarded credits, one payout and one `forward` event each.
+fn add_forwards(
+    deps: DepsMut,
+    env: &Env,
+    res: Response,
+    forwards: Vec<ForwardEvent>,
+) -> Result<Response, ContractError> {
+    let (messages, events) = pay_forwards(deps, env, forwards)?;
+    Ok(res.add_messages(messages).add_events(events))
+}
+
+fn pay_forwards(
+    mut deps: DepsMut,
+    env: &Env,
+    forwards: Vec<ForwardEvent>,
+) -> Result<(Vec<CosmosMsg>, Vec<Event>), ContractError> {
+    let mut messages = vec![];
+    let mut events = vec![];
+    for forward in forwards {
+        let (payout, _) = pay_out(deps.branch(), env, &forward.to, forward.amount)?;
+        messages.extend(payout);
+        events.push(forward.into());
+    }
+    Ok((messages, events))
+}
+
+/// Moves the sender's pending claim from a transfer into their balance.
+pub fn execute_claim(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfer_id: u64,
+) -> Result<Response, ContractError> {
+    let claim = claims().load(deps.storage, (transfer_id, &info.sender))?;
+    if claim.expires.is_expired(&env.block) {
+        return Err(ContractError::ClaimExpired {});
+    }
+    claims().remove(deps.storage, (transfer_id, &info.sender))?;
+    unreserve_funds(deps.storage, claim.amount)?;
+
+    let mut forwards = vec![];
+    let kept = credit_recipient(
+        deps.storage,
+        &env.block,
+        &info.sender,
+        claim.amount,
+        &mut forwards,
+    )?;
+    let cancel_deadline = transfers().load(deps.storage, transfer_id)?.cancel_deadline;
+    if let (true, Some(expires)) = (kept, cancel_deadline) {
+        let credit = CancellableCredit {
+            credited: claim.amount,
+            fee: Uint128::zero(),
+            expires,
+        };
+        note_cancellable(deps.storage, &env.block, &info.sender, transfer_id, credit)?;
+    }
+    let event = ClaimEvent {
+        transfer_id,
+        recipient: info.sender,
+        amount: claim.amount,
+    };
+    let res = Response::new()
+        .add_attribute("action", "claim")
+        .add_event(event.into());
+    add_forwards(deps, &env, res, forwards)
+}
+
+/// Returns every expired, unclaimed share of a transfer to its sender.
+/// Expired claims are sent back, while shares that were never accepted go
+/// back into the sender's balance.
+pub fn execute_reclaim(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfer_id: u64,
+) -> Result<Response, ContractError> {
+    let pending_claims = load_pending(claims(), deps.storage, transfer_id)?;
+    let pending_acceptances = load_pending(acceptances(), deps.storage, transfer_id)?;
+    let first = match pending_claims.first().or(pending_acceptances.first()) {
+        Some(first) => first,
+        None => return Err(ContractError::NothingToReclaim {}),
+    };
+    if first.sender != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut amount = Uint128::zero();
+    for claim in pending_claims
+        .iter()
+        .filter(|claim| claim.expires.is_expired(&env.block))
+    {
+        claims().remove(deps.storage, (transfer_id, &claim.recipient))?;
+        amount += claim.amount;
+    }
+    let mut credited = Uint128::zero();
+    for pending in pending_acceptances
+        .iter()
+        .filter(|pending| pending.expires.is_expired(&env.block))
+    {
+        acceptances().remove(deps.storage, (transfer_id, &pending.recipient))?;
+        credited += pending.amount;
+    }
+    if amount.is_zero() && credited.is_zero() {
+        return Err(ContractError::ClaimNotExpired {});
+    }
+    unreserve_funds(deps.storage, amount + credited)?;
+    add_balance(deps.storage, &info.sender, credited, env.block.height)?;
+
+    let mut res = Response::new().add_attribute("action", "reclaim");
+    if !amount.is_zero() {
+        let (messages, _) = pay_out(deps.branch(), &env, &info.sender, amount)?;
+        res = res.add_messages(messages);
+    }
+    let event = ReclaimEvent {
+        transfer_id,
+        sender: info.sender,
+        amount,
+        credited,
+    };
+    Ok(res.add_event(event.into()))
+}
+
+fn load_pending(
+    pending: PendingMap,
+    storage: &dyn Storage,
+    transfer_id: u64,
+) -> StdResult<Vec<PendingClaim>> {
+    pending
+        .prefix(transfer_id)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, claim)| claim))
+        .collect()
+}
+
+pub fn execute_set_acceptance(
+    deps: DepsMut,
+    info: MessageInfo,
+    window: Option<Duration>,
+) -> Result<Response, ContractError> {
+    match window {
+        Some(window) => ACCEPTANCE_WINDOWS.save(deps.storage, &info.sender, &window)?,
+        None => ACCEPTANCE_WINDOWS.remove(deps.storage, &info.sender),
+    }
+    let event = AcceptanceWindowEvent {
+        address: info.sender,
+        window,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "set_acceptance", event.into(), legacy_attributes)?)
+}
+
+/// Accepts the sender's pending share of a transfer into their balance, or
+/// rejects it back into the transfer sender's balance. Rejecting is allowed
+/// after the window has passed; accepting is not.
+pub fn execute_accept(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfer_id: u64,
+    accepted: bool,
+) -> Result<Response, ContractError> {
+    let pending = acceptances().load(deps.storage, (transfer_id, &info.sender))?;
+    if accepted && pending.expires.is_expired(&env.block) {
+        return Err(ContractError::AcceptanceExpired {});
+    }
+    acceptances().remove(deps.storage, (transfer_id, &info.sender))?;
+    unreserve_funds(deps.storage, pending.amount)?;
+
+    let mut forwards = vec![];
+    if accepted {
+        let kept = credit_recipient(
+            deps.storage,
+            &env.block,
+            &info.sender,
+            pending.amount,
+            &mut forwards,
+        )?;
+        let cancel_deadline = transfers().load(deps.storage, transfer_id)?.cancel_deadline;
+        if let (true, Some(expires)) = (kept, cancel_deadline) {
+            let credit = CancellableCredit {
+                credited: pending.amount,
+                fee: Uint128::zero(),
+                expires,
+            };
+            note_cancellable(deps.storage, &env.block, &info.sender, transfer_id, credit)?;
+        }
+    } else {
+        add_balance(deps.storage, &pending.sender, pending.amount, env.block.height)?;
+    }
+    let action = if accepted { "accept" } else { "reject" };
+    let event = AcceptanceEvent {
+        transfer_id,
+        sender: pending.sender,
+        recipient: info.sender,
+        amount: pending.amount,
+        accepted,
+    };
+    let res = Response::new()
+        .add_attribute("action", action)
+        .add_event(event.into());
+    add_forwards(deps, &env, res, forwards)
+}
+
+/// Reverses a transfer inside its cancel window. Shares still pending are
+/// taken back in full; credited shares and the fee only as far as they have
+/// not been spent since. The coins go back to the sender.
+pub fn execute_cancel_transfer(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfer_id: u64,
+) -> Result<Response, ContractError> {
+    let mut record = transfers().load(deps.storage, transfer_id)?;
+    if record.sender != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if record.cancelled {
+        return Err(ContractError::AlreadyCancelled {});
+    }
+    match record.cancel_deadline {
+        None => return Err(ContractError::NotCancellable {}),
+        Some(deadline) if deadline.is_expired(&env.block) => {
+            return Err(ContractError::CancelWindowClosed {})
+        }
+        Some(_) => {}
+    }
+
+    // A recipient named twice was credited both shares
+    let mut credits = vec![(record.recipient_1.clone(), record.recipient_amount)];
+    if record.recipient_2 == record.recipient_1 {
+        credits[0].1 += record.recipient_amount;
+    } else {
+        credits.push((record.recipient_2.clone(), record.recipient_amount));
+    }
+
+    let mut recipients = vec![];
+    let mut amount = Uint128::zero();
+    for (recipient, credited) in credits {
+        let key = (transfer_id, &recipient);
+        let reversed = if let Some(claim) = claims().may_load(deps.storage, key)? {
+            claims().remove(deps.storage, key)?;
+            unreserve_funds(deps.storage, claim.amount)?;
+            claim.amount
+        } else if let Some(pending) = acceptances().may_load(deps.storage, key)? {
+            acceptances().remove(deps.storage, key)?;
+            unreserve_funds(deps.storage, pending.amount)?;
+            pending.amount
+        } else if let Some(grant) = VESTING.may_load(deps.storage, (&recipient, transfer_id))? {
+            // Unvested shares come back in full, released ones as far as the
+            // recipient has not spent them
+            VESTING.remove(deps.storage, (&recipient, transfer_id));
+            unreserve_funds(deps.storage, grant.total - grant.released)?;
+            let reversed = take_back_credit(deps.storage, &env, &recipient, transfer_id)?;
+            grant.total - grant.released + reversed
+        } else {
+            // Nothing is owed back for shares that were rejected, reclaimed
+            // or already spent
+            take_back_credit(deps.storage, &env, &recipient, transfer_id)?
+        };
+        amount += reversed;
+        recipients.push((recipient, credited, reversed));
+    }
+
+    let mut fee_refunded = Uint128::zero();
+    let owner = OWNER.load(deps.storage)?;
+    let key = (&owner, transfer_id);
+    if let Some(mut credit) = CANCELLABLE_CREDITS.may_load(deps.storage, key)? {
+        if CANCEL_FEE_MODE.may_load(deps.storage)?.unwrap_or_default() == CancelFeeMode::Refund {
+            let balance = BALANCE.may_load(deps.storage, &owner)?.unwrap_or_default();
+            fee_refunded = credit.fee.min(balance);
+            sub_balance(deps.storage, &owner, fee_refunded, env.block.height)?;
+            amount += fee_refunded;
+        }
+        credit.fee = Uint128::zero();
+        if credit.credited.is_zero() {
+            CANCELLABLE_CREDITS.remove(deps.storage, key);
+        } else {
+            CANCELLABLE_CREDITS.save(deps.storage, key, &credit)?;
+        }
+    }
+
+    record.cancelled = true;
+    transfers().save(deps.storage, transfer_id, &record)?;
+
+    let mut res = Response::new().add_attribute("action", "cancel_transfer");
+    if !amount.is_zero() {
+        let (messages, _) = pay_out(deps.branch(), &env, &info.sender, amount)?;
+        res = res.add_messages(messages);
+    }
+    let event = CancelTransferEvent {
+        transfer_id,
+        sender: info.sender,
+        recipients,
+        fee_refunded,
+        amount,
+    };
+    Ok(res.add_event(event.into()))
+}
+
+/// Takes back what is left of `recipient`'s credit from a cancelled transfer.
+fn take_back_credit(
+    storage: &mut dyn Storage,
+    env: &Env,
+    recipient: &Addr,
+    transfer_id: u64,
+) -> Result<Uint128, ContractError> {
+    let key = (recipient, transfer_id);
+    let mut credit = match CANCELLABLE_CREDITS.may_load(storage, key)? {
+        Some(credit) => credit,
+        None => return Ok(Uint128::zero()),
+    };
+    let balance = BALANCE.may_load(storage, recipient)?.unwrap_or_default();
+    let reversed = credit.credited.min(balance);
+    sub_balance(storage, recipient, reversed, env.block.height)?;
+    credit.credited = Uint128::zero();
+    if credit.fee.is_zero() {
+        CANCELLABLE_CREDITS.remove(storage, key);
+    } else {
+        CANCELLABLE_CREDITS.save(storage, key, &credit)?;
+    }
+    Ok(reversed)
+}
+
+pub fn execute_create_stream(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipients: Vec<String>,
+    rate_per_second: Uint128,
+    start: Timestamp,
+    end: Timestamp,
+) -> Result<Response, ContractError> {
+    if recipients.is_empty() {
+        return Err(ContractError::NoRecipients {});
+    }
+    if rate_per_second.is_zero() || end <= start || start < env.block.time {
+        return Err(ContractError::InvalidStream {});
+    }
+    let recipients = recipients
+        .iter()
+        .map(|recipient| deps.api.addr_validate(recipient))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    // The whole stream is paid for up front, along with the usual fee
+    let fee = FEE.load(deps.storage)?;
+    let deposit = rate_per_second
+        .checked_mul(Uint128::from(recipients.len() as u64))?
+        .checked_mul(Uint128::from(end.seconds() - start.seconds()))?;
+    let expected = deposit.checked_add(fee)?;
+    let received = sent_amount(deps.storage, &info.funds)?;
+    if received != expected {
+        return Err(ContractError::IncorrectFunds { expected, received });
+    }
+    for recipient in &recipients {
+        // Ended streams are dropped as they are released, so they don't count
+        release_accrued(deps.storage, &env.block, recipient)?;
+        let streams = STREAMED
+            .prefix(recipient)
+            .keys(deps.storage, None, None, Order::Ascending)
+            .take(MAX_STREAMS)
+            .count();
+        if streams == MAX_STREAMS {
+            return Err(ContractError::TooManyStreams {});
+        }
+    }
+    let owner = OWNER.load(deps.storage)?;
+    add_balance(deps.storage, &owner, fee, env.block.height)?;
+    reserve_funds(deps.storage, deposit)?;
+
+    let stream_id = STREAM_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    STREAM_COUNT.save(deps.storage, &stream_id)?;
+    for recipient in &recipients {
+        STREAMED.save(deps.storage, (recipient, stream_id), &Uint128::zero())?;
+    }
+    let stream = Stream {
+        id: stream_id,
+        sender: info.sender.clone(),
+        recipients,
+        rate_per_second,
+        start,
+        end,
+    };
+    STREAMS.save(deps.storage, stream_id, &stream)?;
+
+    let event = StreamFundedEvent {
+        stream_id,
+        sender: info.sender,
+        amount: deposit,
+        end,
+    };
+    Ok(Response::new()
+        .add_attribute("action", "create_stream")
+        .add_attribute("stream_id", stream_id.to_string())
+        .add_event(event.into()))
+}
+
+pub fn execute_top_up_stream(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    stream_id: u64,
+) -> Result<Response, ContractError> {
+    let mut stream = STREAMS.load(deps.storage, stream_id)?;
+    if stream.sender != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if stream.end <= env.block.time {
+        return Err(ContractError::StreamEnded {});
+    }
+    let per_second = stream.rate_per_second * Uint128::from(stream.recipients.len() as u64);
+    let received = sent_amount(deps.storage, &info.funds)?;
+    let seconds = (received / per_second).u128() as u64;
+    let amount = per_second * Uint128::from(seconds);
+    stream.end = stream.end.plus_seconds(seconds);
+    STREAMS.save(deps.storage, stream_id, &stream)?;
+    reserve_funds(deps.storage, amount)?;
+
+    let mut res = Response::new().add_attribute("action", "top_up_stream");
+    let refund = received - amount;
+    if !refund.is_zero() {
+        let coin_denom = COIN_DENOM.load(deps.storage)?;
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(refund.u128(), &coin_denom),
+        });
+    }
+    let event = StreamFundedEvent {
+        stream_id,
+        sender: info.sender,
+        amount,
+        end: stream.end,
+    };
+    Ok(res.add_event(event.into()))
+}
+
+/// Ends the stream at the current block time; what has already streamed
+/// stays with the recipients.
+pub fn execute_cancel_stream(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    stream_id: u64,
+) -> Result<Response, ContractError> {
+    let mut stream = STREAMS.load(deps.storage, stream_id)?;
+    if stream.sender != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if stream.end <= env.block.time {
+        return Err(ContractError::StreamEnded {});
+    }
+    let end = env.block.time.max(stream.start);
+    let per_second = stream.rate_per_second * Uint128::from(stream.recipients.len() as u64);
+    let refund = per_second * Uint128::from(stream.end.seconds() - end.seconds());
+    stream.end = end;
+    STREAMS.save(deps.storage, stream_id, &stream)?;
+    unreserve_funds(deps.storage, refund)?;
+
+    let mut res = Response::new().add_attribute("action", "cancel_stream");
+    if !refund.is_zero() {
+        let (messages, _) = pay_out(deps.branch(), &env, &info.sender, refund)?;
+        res = res.add_messages(messages);
+    }
+    let event = CancelStreamEvent {
+        stream_id,
+        sender: info.sender,
+        refund,
+    };
+    Ok(res.add_event(event.into()))
+}
+
+pub fn execute_create_schedule(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+    recipients: [String; 2],
+    interval: Duration,
+    count: u32,
+) -> Result<Response, ContractError> {
+    if count == 0 {
+        return Err(ContractError::NoInstalments {});
+    }
+    // Every instalment is planned the same way, so check it once up front
+    let fee = FEE.load(deps.storage)?;
+    let plan = plan_transfer(deps.as_ref(), amount, fee, None, &recipients)?;
+    let [recipient_1, recipient_2] = plan.recipients;
+
+    let bounty = KEEPER_BOUNTY.may_load(deps.storage)?.unwrap_or_default();
+    let expected = amount
+        .checked_add(bounty)?
+        .checked_mul(Uint128::from(count))?;
+    let received = sent_amount(deps.storage, &info.funds)?;
+    if received != expected {
+        return Err(ContractError::IncorrectFunds { expected, received });
+    }
+
+    let schedule_id = SCHEDULE_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    SCHEDULE_COUNT.save(deps.storage, &schedule_id)?;
+    let next_due = match interval {
+        Duration::Height(_) => Expiration::AtHeight(env.block.height),
+        Duration::Time(_) => Expiration::AtTime(env.block.time),
+    };
+    let schedule = Schedule {
+        id: schedule_id,
+        sender: info.sender,
+        amount,
+        recipient_1,
+        recipient_2,
+        interval,
+        remaining: count,
+        next_due,
+        bounty,
+    };
+    SCHEDULES.save(deps.storage, schedule_id, &schedule)?;
+    reserve_funds(deps.storage, expected)?;
+
+    let event = ScheduleCreatedEvent {
+        schedule_id,
+        sender: schedule.sender,
+        recipient_1: schedule.recipient_1,
+        recipient_2: schedule.recipient_2,
+        amount,
+        count,
+        bounty,
+    };
+    Ok(Response::new()
+        .add_attribute("action", "create_schedule")
+        .add_event(event.into()))
+}
+
+/// Pays the next instalment through the same path as `Transfer`, then the
+/// keeper's bounty.
+pub fn execute_due(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    schedule_id: u64,
+) -> Result<Response, ContractError> {
+    let mut schedule = SCHEDULES.load(deps.storage, schedule_id)?;
+    if schedule.remaining == 0 {
+        return Err(ContractError::ScheduleFinished {});
+    }
+    if !schedule.next_due.is_expired(&env.block) {
+        return Err(ContractError::NotDue {
+            next_due: schedule.next_due,
+        });
+    }
+
+    let fee = FEE.load(deps.storage)?;
+    let recipients = [schedule.recipient_1.to_string(), schedule.recipient_2.to_string()];
+    let plan = plan_transfer(deps.as_ref(), schedule.amount, fee, None, &recipients)?;
+    let mut forwards = vec![];
+    let event = apply_transfer(
+        deps.storage,
+        &env,
+        &schedule.sender,
+        &plan,
+        TransferTerms::default(),
+        &mut forwards,
+    )?;
+    let transfer_id = event.transfer_id;
+
+    schedule.remaining -= 1;
+    schedule.next_due = (schedule.next_due + schedule.interval)?;
+    SCHEDULES.save(deps.storage, schedule_id, &schedule)?;
+    unreserve_funds(deps.storage, schedule.amount + schedule.bounty)?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "execute_due")
+        .add_event(event.into());
+    if !schedule.bounty.is_zero() {
+        let (messages, _) = pay_out(deps.branch(), &env, &info.sender, schedule.bounty)?;
+        res = res.add_messages(messages);
+    }
+    if !plan.refund.is_zero() {
+        let (messages, _) = pay_out(deps.branch(), &env, &schedule.sender, plan.refund)?;
+        res = res.add_messages(messages);
+    }
+    let scheduled = ScheduledTransferEvent {
+        schedule_id,
+        transfer_id,
+        keeper: info.sender,
+        bounty: schedule.bounty,
+        remaining: schedule.remaining,
+    };
+    add_forwards(deps, &env, res.add_event(scheduled.into()), forwards)
+}
+
+pub fn execute_cancel_schedule(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    schedule_id: u64,
+) -> Result<Response, ContractError> {
+    let mut schedule = SCHEDULES.load(deps.storage, schedule_id)?;
+    if schedule.sender != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if schedule.remaining == 0 {
+        return Err(ContractError::ScheduleFinished {});
+    }
+    let refund = (schedule.amount + schedule.bounty) * Uint128::from(schedule.remaining);
+    schedule.remaining = 0;
+    SCHEDULES.save(deps.storage, schedule_id, &schedule)?;
+    unreserve_funds(deps.storage, refund)?;
+
+    let (messages, _) = pay_out(deps.branch(), &env, &info.sender, refund)?;
+    let event = CancelScheduleEvent {
+        schedule_id,
+        sender: info.sender,
+        refund,
+    };
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "cancel_schedule")
+        .add_event(event.into()))
+}
+
+/// Plans a `Transfer` as usual but holds the charged funds until
+/// `RevealPreimage`. Anything sent beyond that is refunded now.
+pub fn execute_hash_locked_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfer_amount: Uint128,
+    recipients: [String; 2],
+    hash: String,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    if !matches!(hex::decode(&hash), Ok(bytes) if bytes.len() == 32) {
+        return Err(ContractError::InvalidHash {});
+    }
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    let fee = FEE.load(deps.storage)?;
+    let plan = plan_transfer(deps.as_ref(), transfer_amount, fee, Some(&info.funds), &recipients)?;
+    let [recipient_1, recipient_2] = plan.recipients;
+
+    let lock_id = HASH_LOCK_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    HASH_LOCK_COUNT.save(deps.storage, &lock_id)?;
+    let hash_lock = HashLock {
+        id: lock_id,
+        sender: info.sender.clone(),
+        recipient_1,
+        recipient_2,
+        recipient_amount: plan.recipient_amount,
+        fee: plan.fee,
+        hash: hash.to_lowercase(),
+        expires,
+    };
+    HASH_LOCKS.save(deps.storage, lock_id, &hash_lock)?;
+    reserve_funds(deps.storage, plan.sender_charged)?;
+
+    let event = HashLockCreatedEvent {
+        lock_id,
+        sender: hash_lock.sender,
+        recipient_1: hash_lock.recipient_1,
+        recipient_2: hash_lock.recipient_2,
+        recipient_amount: hash_lock.recipient_amount,
+        fee: hash_lock.fee,
+        hash: hash_lock.hash,
+        expires,
+    };
+    let mut res = Response::new()
+        .add_attribute("action", "hash_locked_transfer")
+        .add_event(event.into());
+    if !plan.refund.is_zero() {
+        let coin_denom = COIN_DENOM.load(deps.storage)?;
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(plan.refund.u128(), &coin_denom),
+        });
+    }
+    Ok(res)
+}
+
+/// Turns a hash lock into a completed transfer, crediting the recipients and
+/// the owner's fee.
+pub fn execute_reveal_preimage(
+    deps: DepsMut,
+    env: Env,
+    lock_id: u64,
+    preimage: String,
+) -> Result<Response, ContractError> {
+    let hash_lock = HASH_LOCKS.load(deps.storage, lock_id)?;
+    if hash_lock.expires.is_expired(&env.block) {
+        return Err(ContractError::HashLockExpired {});
+    }
+    let preimage_bytes = hex::decode(&preimage).map_err(|_| ContractError::InvalidPreimage {})?;
+    if hex::encode(Sha256::digest(preimage_bytes)) != hash_lock.hash {
+        return Err(ContractError::InvalidPreimage {});
+    }
+    HASH_LOCKS.remove(deps.storage, lock_id);
+
+    let plan = TransferPlan {
+        recipients: [hash_lock.recipient_1, hash_lock.recipient_2],
+        fee: hash_lock.fee,
+        recipient_amount: hash_lock.recipient_amount,
+        sender_charged: hash_lock.fee + hash_lock.recipient_amount * Uint128::new(2),
+        refund: Uint128::zero(),
+    };
+    unreserve_funds(deps.storage, plan.sender_charged)?;
+    let mut forwards = vec![];
+    let event = apply_transfer(
+        deps.storage,
+        &env,
+        &hash_lock.sender,
+        &plan,
+        TransferTerms::default(),
+        &mut forwards,
+    )?;
+    let released = HashLockReleasedEvent {
+        lock_id,
+        transfer_id: event.transfer_id,
+        preimage,
+    };
+    let res = Response::new()
+        .add_attribute("action", "reveal_preimage")
+        .add_event(event.into())
+        .add_event(released.into());
+    add_forwards(deps, &env, res, forwards)
+}
+
+/// Sends an expired hash lock's funds back to its sender. Anyone can call
+/// this, as the funds can only go one way.
+pub fn execute_refund_hash_lock(
+    mut deps: DepsMut,
+    env: Env,
+    lock_id: u64,
+) -> Result<Response, ContractError> {
+    let hash_lock = HASH_LOCKS.load(deps.storage, lock_id)?;
+    if !hash_lock.expires.is_expired(&env.block) {
+        return Err(ContractError::HashLockNotExpired {});
+    }
+    HASH_LOCKS.remove(deps.storage, lock_id);
+
+    let amount = hash_lock.fee + hash_lock.recipient_amount * Uint128::new(2);
+    unreserve_funds(deps.storage, amount)?;
+    let (messages, _) = pay_out(deps.branch(), &env, &hash_lock.sender, amount)?;
+    let event = RefundHashLockEvent {
+        lock_id,
+        sender: hash_lock.sender,
+        amount,
+    };
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "refund_hash_lock")
+        .add_event(event.into()))
+}
+
+/// The fee is charged when the escrow is funded; the rest is held until
+/// released or refunded.
+pub fn execute_create_escrow(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipients: [String; 2],
+    arbiter: String,
+    joint_approval: bool,
+) -> Result<Response, ContractError> {
+    let arbiter = deps.api.addr_validate(&arbiter)?;
+    let amount = sent_amount(deps.storage, &info.funds)?;
+    let fee = FEE.load(deps.storage)?;
+    let plan = plan_transfer(deps.as_ref(), amount, fee, None, &recipients)?;
+    let [recipient_1, recipient_2] = plan.recipients;
+    let owner = OWNER.load(deps.storage)?;
+    add_balance(deps.storage, &owner, plan.fee, env.block.height)?;
+
+    let escrow_id = ESCROW_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    ESCROW_COUNT.save(deps.storage, &escrow_id)?;
+    let escrow = Escrow {
+        id: escrow_id,
+        sender: info.sender.clone(),
+        arbiter,
+        recipient_1,
+        recipient_2,
+        joint_approval,
+        remaining: plan.sender_charged - plan.fee,
+        released: Uint128::zero(),
+        refunded: Uint128::zero(),
+        pending_release: None,
+    };
+    ESCROWS.save(deps.storage, escrow_id, &escrow)?;
+    reserve_funds(deps.storage, escrow.remaining)?;
+
+    let event = EscrowCreatedEvent {
+        escrow_id,
+        sender: escrow.sender,
+        arbiter: escrow.arbiter,
+        recipient_1: escrow.recipient_1,
+        recipient_2: escrow.recipient_2,
+        joint_approval,
+        amount: escrow.remaining,
+        fee: plan.fee,
+    };
+    let mut res = Response::new()
+        .add_attribute("action", "create_escrow")
+        .add_event(event.into());
+    if !plan.refund.is_zero() {
+        let coin_denom = COIN_DENOM.load(deps.storage)?;
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(plan.refund.u128(), &coin_denom),
+        });
+    }
+    Ok(res)
+}
+
+/// Credits a release to the recipients' balances. An odd `amount` is
+/// rounded down so both get the same.
+pub fn execute_release_escrow(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    escrow_id: u64,
+    amount: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut escrow = ESCROWS.load(deps.storage, escrow_id)?;
+    if info.sender != escrow.arbiter && info.sender != escrow.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let amount = amount.unwrap_or(escrow.remaining);
+    if amount > escrow.remaining {
+        return Err(ContractError::ExceedsEscrow {
+            remaining: escrow.remaining,
+        });
+    }
+    let recipient_amount = amount / Uint128::new(2);
+    if recipient_amount.is_zero() {
+        return Err(ContractError::RecipientPaidZeroOrOneCoin {});
+    }
+
+    // Under joint approval a release needs matching calls from both parties
+    if escrow.joint_approval {
+        let approved = matches!(
+            &escrow.pending_release,
+            Some(pending) if pending.amount == amount && pending.approved_by != info.sender
+        );
+        if !approved {
+            escrow.pending_release = Some(PendingRelease {
+                amount,
+                approved_by: info.sender.clone(),
+            });
+            ESCROWS.save(deps.storage, escrow_id, &escrow)?;
+            let event = ApproveReleaseEvent {
+                escrow_id,
+                approved_by: info.sender,
+                amount,
+            };
+            let legacy_attributes = event.legacy_attributes();
+            return Ok(event_response(
+                deps.storage,
+                "approve_release",
+                event.into(),
+                legacy_attributes,
+            )?);
+        }
+        escrow.pending_release = None;
+    }
+
+    let mut forwards = vec![];
+    for recipient in [&escrow.recipient_1, &escrow.recipient_2] {
+        credit_recipient(deps.storage, &env.block, recipient, recipient_amount, &mut forwards)?;
+    }
+    let released = recipient_amount * Uint128::new(2);
+    escrow.remaining -= released;
+    escrow.released += released;
+    ESCROWS.save(deps.storage, escrow_id, &escrow)?;
+    unreserve_funds(deps.storage, released)?;
+
+    let event = EscrowReleaseEvent {
+        escrow_id,
+        recipient_1: escrow.recipient_1,
+        recipient_2: escrow.recipient_2,
+        recipient_amount,
+        remaining: escrow.remaining,
+    };
+    let res = Response::new()
+        .add_attribute("action", "release_escrow")
+        .add_event(event.into());
+    add_forwards(deps, &env, res, forwards)
+}
+
+pub fn execute_refund_escrow(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    escrow_id: u64,
+) -> Result<Response, ContractError> {
+    let mut escrow = ESCROWS.load(deps.storage, escrow_id)?;
+    if info.sender != escrow.arbiter {
+        return Err(ContractError::Unauthorized {});
+    }
+    let refund = escrow.remaining;
+    escrow.remaining = Uint128::zero();
+    escrow.refunded += refund;
+    escrow.pending_release = None;
+    ESCROWS.save(deps.storage, escrow_id, &escrow)?;
+    unreserve_funds(deps.storage, refund)?;
+
+    let event = RefundEscrowEvent {
+        escrow_id,
+        sender: escrow.sender.clone(),
+        refund,
+    };
+    let mut res = Response::new()
+        .add_attribute("action", "refund_escrow")
+        .add_event(event.into());
+    if !refund.is_zero() {
+        let (messages, _) = pay_out(deps.branch(), &env, &escrow.sender, refund)?;
+        res = res.add_messages(messages);
+    }
+    Ok(res)
+}
+
+pub fn execute_create_invoice(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+    denom: String,
+    co_recipient: Option<String>,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    if denom != coin_denom {
+        return Err(ContractError::UnsupportedDenom { denom: coin_denom });
+    }
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    // Check now that the invoice can be paid as a transfer
+    let recipients = [
+        info.sender.to_string(),
+        co_recipient.unwrap_or_else(|| info.sender.to_string()),
+    ];
+    let fee = FEE.load(deps.storage)?;
+    let plan = plan_transfer(deps.as_ref(), amount, fee, None, &recipients)?;
+    let [recipient_1, recipient_2] = plan.recipients;
+
+    let invoice_id = INVOICE_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    INVOICE_COUNT.save(deps.storage, &invoice_id)?;
+    let invoice = Invoice {
+        id: invoice_id,
+        creator: info.sender,
+        amount,
+        denom,
+        recipient_1,
+        recipient_2,
+        expires,
+        paid_by: None,
+        transfer_id: None,
+    };
+    INVOICES.save(deps.storage, invoice_id, &invoice)?;
+
+    let event = InvoiceCreatedEvent {
+        invoice_id,
+        creator: invoice.creator,
+        recipient_1: invoice.recipient_1,
+        recipient_2: invoice.recipient_2,
+        amount,
+        expires,
+    };
+    Ok(Response::new()
+        .set_data(to_json_binary(&invoice_id)?)
+        .add_attribute("action", "create_invoice")
+        .add_event(event.into()))
+}
+
+/// Pays an invoice as a `Transfer` from the payer, fee and all.
+pub fn execute_pay_invoice(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    invoice_id: u64,
+) -> Result<Response, ContractError> {
+    let mut invoice = INVOICES.load(deps.storage, invoice_id)?;
+    if invoice.paid_by.is_some() {
+        return Err(ContractError::InvoicePaid {});
+    }
+    if invoice.expires.is_expired(&env.block) {
+        return Err(ContractError::InvoiceExpired {});
+    }
+    let received = sent_amount(deps.storage, &info.funds)?;
+    if received != invoice.amount {
+        return Err(ContractError::IncorrectFunds {
+            expected: invoice.amount,
+            received,
+        });
+    }
+
+    let fee = FEE.load(deps.storage)?;
+    let recipients = [invoice.recipient_1.to_string(), invoice.recipient_2.to_string()];
+    let plan = plan_transfer(deps.as_ref(), invoice.amount, fee, Some(&info.funds), &recipients)?;
+    let mut forwards = vec![];
+    let event = apply_transfer(
+        deps.storage,
+        &env,
+        &info.sender,
+        &plan,
+        TransferTerms::default(),
+        &mut forwards,
+    )?;
+    invoice.paid_by = Some(info.sender.clone());
+    invoice.transfer_id = Some(event.transfer_id);
+    INVOICES.save(deps.storage, invoice_id, &invoice)?;
+
+    let paid = InvoicePaidEvent {
+        invoice_id,
+        transfer_id: event.transfer_id,
+        payer: info.sender.clone(),
+    };
+    let mut res = Response::new()
+        .add_attribute("action", "pay_invoice")
+        .add_event(event.into())
+        .add_event(paid.into());
+    if !plan.refund.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(plan.refund.u128(), &invoice.denom),
+        });
+    }
+    add_forwards(deps, &env, res, forwards)
+}
+
+/// Owner only. Moves existing delegations to a new validator, or undelegates
+/// them all when staking is turned off.
+pub fn execute_set_staking(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    config: Option<StakingConfig>,
+) -> Result<Response, ContractError> {
+    if info.sender != OWNER.load(deps.storage)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let delegated = DELEGATED.may_load(deps.storage)?.unwrap_or_default();
+    let previous = STAKING.may_load(deps.storage)?;
+
+    let mut messages = vec![];
+    match (previous, &config) {
+        (Some(previous), None) if !delegated.is_zero() => {
+            book_rewards(deps.branch(), &env, &previous.validator)?;
+            messages.push(StakingMsg::Undelegate {
+                validator: previous.validator,
+                amount: coin(delegated.u128(), &coin_denom),
+            });
+            DELEGATED.save(deps.storage, &Uint128::zero())?;
+            let unbonding_period =
+                UNBONDING_PERIOD.may_load(deps.storage)?.unwrap_or(DEFAULT_UNBONDING_PERIOD);
+            UNBONDING_UNTIL.save(deps.storage, &unbonding_period.after(&env.block))?;
+        }
+        (Some(previous), Some(config))
+            if !delegated.is_zero() && previous.validator != config.validator =>
+        {
+            book_rewards(deps.branch(), &env, &previous.validator)?;
+            book_rewards(deps.branch(), &env, &config.validator)?;
+            messages.push(StakingMsg::Redelegate {
+                src_validator: previous.validator,
+                dst_validator: config.validator.clone(),
+                amount: coin(delegated.u128(), &coin_denom),
+            });
+        }
+        _ => {}
+    }
+    match &config {
+        Some(config) => STAKING.save(deps.storage, config)?,
+        None => STAKING.remove(deps.storage),
+    }
+
+    let event = SetStakingEvent { config };
+    let legacy_attributes = event.legacy_attributes();
+    let res = event_response(deps.storage, "set_staking", event.into(), legacy_attributes)?;
+    Ok(res.add_messages(messages))
+}
+
+/// Liquid funds held by the contract, less what is owed to queued
+/// withdrawals and what is reserved for escrows and the like.
+fn liquid_balance(deps: Deps, env: &Env) -> StdResult<Uint128> {
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let held = deps.querier.query_balance(&env.contract.address, coin_denom)?.amount;
+    let queued = QUEUED_TOTAL.may_load(deps.storage)?.unwrap_or_default();
+    let reserved = RESERVED.may_load(deps.storage)?.unwrap_or_default();
+    Ok(held.saturating_sub(queued).saturating_sub(reserved))
+}
+
+/// Sets aside funds that are held for someone but not yet in a balance.
+fn reserve_funds(storage: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    let reserved = RESERVED.may_load(storage)?.unwrap_or_default();
+    RESERVED.save(storage, &reserved.checked_add(amount)?)
+}
+
+/// Frees reserved funds as they are credited to a balance or paid out.
+fn unreserve_funds(storage: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    let reserved = RESERVED.may_load(storage)?.unwrap_or_default();
+    RESERVED.save(storage, &reserved.saturating_sub(amount))
+}
+
+/// Delegates whatever liquid funds sit above the configured buffer.
+pub fn execute_rebalance(mut deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config = STAKING.may_load(deps.storage)?.ok_or(ContractError::StakingDisabled {})?;
+    let surplus = liquid_balance(deps.as_ref(), &env)?.saturating_sub(config.buffer);
+
+    let event = RebalanceEvent { delegated: surplus };
+    let legacy_attributes = event.legacy_attributes();
+    let mut res = event_response(deps.storage, "rebalance", event.into(), legacy_attributes)?;
+    if !surplus.is_zero() {
+        book_rewards(deps.branch(), &env, &config.validator)?;
+        let coin_denom = COIN_DENOM.load(deps.storage)?;
+        let delegated = DELEGATED.may_load(deps.storage)?.unwrap_or_default();
+        DELEGATED.save(deps.storage, &delegated.checked_add(surplus)?)?;
+        res = res.add_message(StakingMsg::Delegate {
+            validator: config.validator,
+            amount: coin(surplus.u128(), &coin_denom),
+        });
+    }
+    Ok(res)
+}
+
+/// Withdraws rewards from the staking validator and shares them across
+/// balances.
+pub fn execute_harvest_rewards(mut deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config = STAKING.may_load(deps.storage)?.ok_or(ContractError::StakingDisabled {})?;
+    let rewards = book_rewards(deps.branch(), &env, &config.validator)?;
+
+    let withdrawal = DistributionMsg::WithdrawDelegatorReward {
+        validator: config.validator.clone(),
+    };
+    let event = HarvestRewardsEvent {
+        validator: config.validator,
+        rewards,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let res = event_response(deps.storage, "harvest_rewards", event.into(), legacy_attributes)?;
+    Ok(res.add_message(withdrawal))
+}
+
+/// Books the rewards the contract has accrued with `validator`. Any staking
+/// message withdraws them, so this runs before each one. The pooled
+/// delegation's part is shared across balances; what it earns on funds that
+/// are not in a balance stays with the contract.
+fn book_rewards(deps: DepsMut, env: &Env, validator: &str) -> StdResult<Uint128> {
+    // Later messages in the same block find nothing left to book
+    if REWARDS_BOOKED.may_load(deps.storage, validator)? == Some(env.block.height) {
+        return Ok(Uint128::zero());
+    }
+    REWARDS_BOOKED.save(deps.storage, validator, &env.block.height)?;
+
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let rewards: Uint128 = match deps.querier.query_delegation(&env.contract.address, validator)? {
+        Some(delegation) => delegation
+            .accumulated_rewards
+            .iter()
+            .filter(|reward| reward.denom == coin_denom)
+            .map(|reward| reward.amount)
+            .sum(),
+        None => Uint128::zero(),
+    };
+    let pooled = match STAKING.may_load(deps.storage)? {
+        Some(config) if config.validator == validator => {
+            DELEGATED.may_load(deps.storage)?.unwrap_or_default()
+        }
+        _ => Uint128::zero(),
+    };
+    let user_delegated = USER_DELEGATED.may_load(deps.storage, validator)?.unwrap_or_default();
+    if rewards.is_zero() || (pooled + user_delegated).is_zero() {
+        return Ok(rewards);
+    }
+
+    let pooled_rewards = rewards.multiply_ratio(pooled, pooled + user_delegated);
+    let total = TOTAL_BALANCE.may_load(deps.storage)?.unwrap_or_default();
+    if !pooled_rewards.is_zero() && !total.is_zero() {
+        let per_share = REWARD_PER_SHARE.may_load(deps.storage)?.unwrap_or_default();
+        let earned = Decimal::from_ratio(pooled_rewards, total.max(pooled));
+        REWARD_PER_SHARE.save(deps.storage, &(per_share + earned))?;
+    }
+
+    // Users' own delegations to the validator earn the rest
+    let user_rewards = rewards - pooled_rewards;
+    if !user_rewards.is_zero() {
+        let per_share = DELEGATION_REWARD_PER_SHARE.may_load(deps.storage, validator)?.unwrap_or_default();
+        let earned = Decimal::from_ratio(user_rewards, user_delegated);
+        DELEGATION_REWARD_PER_SHARE.save(deps.storage, validator, &(per_share + earned))?;
+    }
+    Ok(rewards)
+}
+
+/// Brings `address`'s pending rewards up to date for the `balance` it held
+/// since it was last settled.
+fn settle_rewards(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    balance: Uint128,
+) -> StdResult<RewardInfo> {
+    let per_share = REWARD_PER_SHARE.may_load(storage)?.unwrap_or_default();
+    let mut info = REWARDS.may_load(storage, address)?.unwrap_or_default();
+    if info.index == per_share {
+        return Ok(info);
+    }
+    info.pending += balance * (per_share - info.index);
+    info.index = per_share;
+    REWARDS.save(storage, address, &info)?;
+    Ok(info)
+}
+
+pub fn execute_claim_rewards(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    release_accrued(deps.storage, &env.block, &info.sender)?;
+
+    // What the sender's own delegations earned is withdrawn and credited too
+    let validators = USER_DELEGATIONS
+        .prefix(&info.sender)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut withdrawals = vec![];
+    for validator in validators {
+        book_rewards(deps.branch(), &env, &validator)?;
+        settle_delegation_rewards(deps.storage, &info.sender, &validator)?;
+        withdrawals.push(DistributionMsg::WithdrawDelegatorReward { validator });
+    }
+
+    let balance = BALANCE.may_load(deps.storage, &info.sender)?.unwrap_or_default();
+    let mut rewards = settle_rewards(deps.storage, &info.sender, balance)?;
+    let amount = rewards.pending;
+    if amount.is_zero() {
+        return Err(ContractError::NoRewards {});
+    }
+    rewards.pending = Uint128::zero();
+    REWARDS.save(deps.storage, &info.sender, &rewards)?;
+
+    let (messages, queued) = pay_out(deps.branch(), &env, &info.sender, amount)?;
+    let event = ClaimRewardsEvent {
+        recipient: info.sender,
+        amount,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let mut res = event_response(deps.storage, "claim_rewards", event.into(), legacy_attributes)?
+        .add_messages(withdrawals)
+        .add_messages(messages);
+    if queued {
+        res = res.add_attribute("queued", "true");
+    }
+    Ok(res)
+}
+
+pub fn execute_delegate_balance(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::ZeroDelegation {});
+    }
+    // Like `Rebalance`, only what is liquid above the buffer can be bonded
+    let buffer = STAKING.may_load(deps.storage)?.map(|config| config.buffer).unwrap_or_default();
+    let available = liquid_balance(deps.as_ref(), &env)?.saturating_sub(buffer);
+    if amount > available {
+        return Err(ContractError::NotEnoughLiquid { available });
+    }
+    spend_balance(deps.storage, &env.block, &info.sender, amount)?;
+    book_rewards(deps.branch(), &env, &validator)?;
+    add_delegation(deps.storage, &info.sender, &validator, amount)?;
+
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let message = StakingMsg::Delegate {
+        validator: validator.clone(),
+        amount: coin(amount.u128(), &coin_denom),
+    };
+    let event = DelegationEvent {
+        delegator: info.sender,
+        validator,
+        amount,
+        delegated: true,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let res = event_response(deps.storage, "delegate_balance", event.into(), legacy_attributes)?;
+    Ok(res.add_message(message))
+}
+
+/// Credits `delegator` with what their delegation to `validator` has earned
+/// since it was last settled. Rewards must be booked first.
+fn settle_delegation_rewards(
+    storage: &mut dyn Storage,
+    delegator: &Addr,
+    validator: &str,
+) -> StdResult<()> {
+    let per_share = DELEGATION_REWARD_PER_SHARE.may_load(storage, validator)?.unwrap_or_default();
+    let index = DELEGATION_REWARD_INDEX
+        .may_load(storage, (delegator, validator))?
+        .unwrap_or_default();
+    if index == per_share {
+        return Ok(());
+    }
+    let delegated = USER_DELEGATIONS
+        .may_load(storage, (delegator, validator))?
+        .unwrap_or_default();
+    let mut info = REWARDS.may_load(storage, delegator)?.unwrap_or_default();
+    info.pending += delegated * (per_share - index);
+    REWARDS.save(storage, delegator, &info)?;
+    DELEGATION_REWARD_INDEX.save(storage, (delegator, validator), &per_share)
+}
+
+/// Adds `amount` to the sender's delegation to `validator`.
+fn add_delegation(
+    storage: &mut dyn Storage,
+    delegator: &Addr,
+    validator: &str,
+    amount: Uint128,
+) -> StdResult<()> {
+    settle_delegation_rewards(storage, delegator, validator)?;
+    USER_DELEGATIONS.update(storage, (delegator, validator), |delegated| -> StdResult<_> {
+        Ok(delegated.unwrap_or_default().checked_add(amount)?)
+    })?;
+    USER_DELEGATED.update(storage, validator, |delegated| -> StdResult<_> {
+        Ok(delegated.unwrap_or_default().checked_add(amount)?)
+    })?;
+    Ok(())
+}
+
+/// Takes `amount` off the sender's delegation to `validator`.
+fn reduce_delegation(
+    storage: &mut dyn Storage,
+    delegator: &Addr,
+    validator: &str,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::ZeroDelegation {});
+    }
+    let delegated = USER_DELEGATIONS
+        .may_load(storage, (delegator, validator))?
+        .unwrap_or_default();
+    if delegated < amount {
+        return Err(ContractError::NotEnoughDelegated { delegated });
+    }
+    settle_delegation_rewards(storage, delegator, validator)?;
+    if delegated == amount {
+        USER_DELEGATIONS.remove(storage, (delegator, validator));
+    } else {
+        USER_DELEGATIONS.save(storage, (delegator, validator), &(delegated - amount))?;
+    }
+    let total = USER_DELEGATED.load(storage, validator)?;
+    if total == amount {
+        USER_DELEGATED.remove(storage, validator);
+    } else {
+        USER_DELEGATED.save(storage, validator, &(total - amount))?;
+    }
+    Ok(())
+}
+
+pub fn execute_undelegate_balance(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    book_rewards(deps.branch(), &env, &validator)?;
+    reduce_delegation(deps.storage, &info.sender, &validator, amount)?;
+
+    // Unbonded coins come back to the contract, so they wait in the queue
+    queue_withdrawal(deps.storage, &env, &info.sender, amount)?;
+
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let message = StakingMsg::Undelegate {
+        validator: validator.clone(),
+        amount: coin(amount.u128(), &coin_denom),
+    };
+    let event = DelegationEvent {
+        delegator: info.sender,
+        validator,
+        amount,
+        delegated: false,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let res = event_response(deps.storage, "undelegate_balance", event.into(), legacy_attributes)?;
+    Ok(res.add_message(message))
+}
+
+pub fn execute_redelegate_balance(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    src_validator: String,
+    dst_validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    book_rewards(deps.branch(), &env, &src_validator)?;
+    book_rewards(deps.branch(), &env, &dst_validator)?;
+    reduce_delegation(deps.storage, &info.sender, &src_validator, amount)?;
+    add_delegation(deps.storage, &info.sender, &dst_validator, amount)?;
+
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let message = StakingMsg::Redelegate {
+        src_validator: src_validator.clone(),
+        dst_validator: dst_validator.clone(),
+        amount: coin(amount.u128(), &coin_denom),
+    };
+    let event = RedelegationEvent {
+        delegator: info.sender,
+        src_validator,
+        dst_validator,
+        amount,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let res = event_response(deps.storage, "redelegate_balance", event.into(), legacy_attributes)?;
+    Ok(res.add_message(message))
+}
+
+pub fn execute_withdraw_ibc(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    remote_address: String,
+    amount: Uint128,
+    timeout: Timestamp,
+) -> Result<Response, ContractError> {
+    if timeout <= env.block.time {
+        return Err(ContractError::InvalidExpiration {});
+    }
+    // A transfer cannot wait in the withdrawal queue, so it must be covered
+    // now without touching reserved or queued funds
+    let available = liquid_balance(deps.as_ref(), &env)?;
+    if amount > available {
+        return Err(ContractError::NotEnoughLiquid { available });
+    }
+    spend_balance(deps.storage, &env.block, &info.sender, amount)?;
+
+    // The packet sequence is only known once the transfer is sent
+    let withdrawal = IbcWithdrawal {
+        sender: info.sender.clone(),
+        channel_id: channel_id.clone(),
+        remote_address: remote_address.clone(),
+        amount,
+    };
+    IBC_SENDING.save(deps.storage, &withdrawal)?;
+
+    // ibc-hooks reports the transfer's outcome back through `sudo`
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let memo = format!(r#"{{"ibc_callback":"{}"}}"#, env.contract.address);
+    let message = CosmosMsg::Stargate {
+        type_url: MSG_TRANSFER_TYPE_URL.to_owned(),
+        value: encode_msg_transfer(
+            &channel_id,
+            &coin(amount.u128(), &coin_denom),
+            env.contract.address.as_str(),
+            &remote_address,
+            timeout,
+            &memo,
+        ),
+    };
+    let event = IbcWithdrawEvent {
+        sender: info.sender,
+        channel_id,
+        remote_address,
+        amount,
+    };
+    Ok(Response::new()
+        .add_attribute("action", "withdraw_ibc")
+        .add_event(event.into())
+        .add_submessage(SubMsg::reply_on_success(message, IBC_TRANSFER_REPLY_ID)))
+}
+
+/// Appends `value` to `buf` as a protobuf varint.
+fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Appends a length-delimited protobuf field to `buf`.
+fn encode_bytes(buf: &mut Vec<u8>, field: u8, bytes: &[u8]) {
+    buf.push((field << 3) | 2);
+    encode_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Protobuf-encodes an ICS20 `MsgTransfer` from the transfer port.
+fn encode_msg_transfer(
+    channel_id: &str,
+    token: &Coin,
+    sender: &str,
+    receiver: &str,
+    timeout: Timestamp,
+    memo: &str,
+) -> Binary {
+    let mut amount = vec![];
+    encode_bytes(&mut amount, 1, token.denom.as_bytes());
+    encode_bytes(&mut amount, 2, token.amount.to_string().as_bytes());
+
+    let mut buf = vec![];
+    encode_bytes(&mut buf, 1, b"transfer");
+    encode_bytes(&mut buf, 2, channel_id.as_bytes());
+    encode_bytes(&mut buf, 3, &amount);
+    encode_bytes(&mut buf, 4, sender.as_bytes());
+    encode_bytes(&mut buf, 5, receiver.as_bytes());
+    buf.push(7 << 3);
+    encode_varint(&mut buf, timeout.nanos());
+    encode_bytes(&mut buf, 8, memo.as_bytes());
+    Binary::from(buf)
+}
+
+/// Reads the packet sequence out of a protobuf-encoded `MsgTransferResponse`.
+fn transfer_sequence(data: &[u8]) -> Option<u64> {
+    let (tag, varint) = data.split_first()?;
+    if *tag != 0x08 {
+        return None;
+    }
+    let mut sequence = 0u64;
+    for (i, byte) in varint.iter().enumerate().take(10) {
+        sequence |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(sequence);
+        }
+    }
+    None
+}
+
+fn reply_ibc_transfer(deps: DepsMut, result: SubMsgResult) -> Result<Response, ContractError> {
+    let data = result.into_result().map_err(StdError::generic_err)?.data;
+    let sequence = data
+        .and_then(|data| transfer_sequence(data.as_slice()))
+        .ok_or(ContractError::InvalidTransferResponse {})?;
+    let withdrawal = IBC_SENDING.load(deps.storage)?;
+    IBC_SENDING.remove(deps.storage);
+    IBC_WITHDRAWALS.save(deps.storage, (&withdrawal.channel_id, sequence), &withdrawal)?;
+    Ok(Response::new().add_attribute("sequence", sequence.to_string()))
+}
+
+/// Credits an in-flight IBC withdrawal back to its sender.
+fn refund_ibc_withdrawal(
+    deps: DepsMut,
+    env: Env,
+    channel_id: &str,
+    sequence: u64,
+    reason: String,
+) -> Result<Response, ContractError> {
+    let withdrawal = match IBC_WITHDRAWALS.may_load(deps.storage, (channel_id, sequence))? {
+        Some(withdrawal) => withdrawal,
+        None => return Ok(Response::new()),
+    };
+    IBC_WITHDRAWALS.remove(deps.storage, (channel_id, sequence));
+    add_balance(deps.storage, &withdrawal.sender, withdrawal.amount, env.block.height)?;
+
+    let event = IbcRefundEvent {
+        sender: withdrawal.sender,
+        channel_id: withdrawal.channel_id,
+        sequence,
+        amount: withdrawal.amount,
+        reason,
+    };
+    Ok(Response::new()
+        .add_attribute("action", "ibc_refund")
+        .add_event(event.into()))
+}
+
+/// Called by ibc-hooks once an IBC withdrawal is acknowledged or times out.
+/// A failed acknowledgement or a timeout means the coins came back, so the
+/// balance is restored.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcAck {
+            channel,
+            sequence,
+            ack,
+            success,
+        }) => {
+            if !success {
+                return refund_ibc_withdrawal(deps, env, &channel, sequence, ack);
+            }
+            IBC_WITHDRAWALS.remove(deps.storage, (&channel, sequence));
+            Ok(Response::new().add_attribute("action", "ibc_ack"))
+        }
+        SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout { channel, sequence }) => {
+            refund_ibc_withdrawal(deps, env, &channel, sequence, "timeout".to_owned())
+        }
+    }
+}
+
+/// This contract sends no packets over its own channels, so there is nothing
+/// to settle.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_ack(
+    _deps: DepsMut,
+    _env: Env,
+    _msg: IbcPacketAckMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    Ok(IbcBasicResponse::new())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_timeout(
+    _deps: DepsMut,
+    _env: Env,
+    _msg: IbcPacketTimeoutMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    Ok(IbcBasicResponse::new())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_open(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelOpenMsg,
+) -> Result<IbcChannelOpenResponse, ContractError> {
+    let channel = msg.channel();
+    if channel.order != IbcOrder::Unordered {
+        return Err(ContractError::InvalidIbcOrder {});
+    }
+    let counterparty_version = msg.counterparty_version().unwrap_or(IBC_VERSION);
+    if channel.version != IBC_VERSION || counterparty_version != IBC_VERSION {
+        return Err(ContractError::InvalidIbcVersion {
+            version: IBC_VERSION.to_owned(),
+        });
+    }
+    let counterparty_port = IBC_COUNTERPARTY_PORT.may_load(deps.storage)?.unwrap_or_default();
+    if counterparty_port.is_empty() || channel.counterparty_endpoint.port_id != counterparty_port {
+        return Err(ContractError::InvalidCounterparty {});
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_connect(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelConnectMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    let channel = msg.channel();
+    IBC_CHANNELS.save(deps.storage, &channel.endpoint.channel_id, &channel.counterparty_endpoint)?;
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_connect")
+        .add_attribute("channel_id", &channel.endpoint.channel_id))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_close(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelCloseMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    let channel = msg.channel();
+    IBC_CHANNELS.remove(deps.storage, &channel.endpoint.channel_id);
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_close")
+        .add_attribute("channel_id", &channel.endpoint.channel_id))
+}
+
+pub fn execute_fund_remote_escrow(
+    deps: DepsMut,
+    info: MessageInfo,
+    channel_id: String,
+    remote_sender: String,
+) -> Result<Response, ContractError> {
+    if !IBC_CHANNELS.has(deps.storage, &channel_id) {
+        return Err(ContractError::UnknownChannel {});
+    }
+    let amount = sent_amount(deps.storage, &info.funds)?;
+    let key = (channel_id.as_str(), remote_sender.as_str());
+    let escrowed = match REMOTE_ESCROWS.may_load(deps.storage, key)? {
+        // Only the funder can add to an escrow it may take back
+        Some(escrow) if escrow.funder != info.sender && !escrow.amount.is_zero() => {
+            return Err(ContractError::Unauthorized {});
+        }
+        Some(escrow) if escrow.funder == info.sender => escrow.amount.checked_add(amount)?,
+        _ => amount,
+    };
+    let escrow = RemoteEscrow {
+        funder: info.sender.clone(),
+        amount: escrowed,
+    };
+    REMOTE_ESCROWS.save(deps.storage, key, &escrow)?;
+    reserve_funds(deps.storage, amount)?;
+
+    let event = FundRemoteEscrowEvent {
+        channel_id,
+        remote_sender,
+        funder: info.sender,
+        amount,
+        escrowed,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "fund_remote_escrow", event.into(), legacy_attributes)?)
+}
+
+/// Gives the funder back what is left of a remote escrow. While the channel is
+/// open the remote sender may still spend it, so this waits for it to close.
+pub fn execute_refund_remote_escrow(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    remote_sender: String,
+) -> Result<Response, ContractError> {
+    let key = (channel_id.as_str(), remote_sender.as_str());
+    let escrow = REMOTE_ESCROWS
+        .may_load(deps.storage, key)?
+        .filter(|escrow| !escrow.amount.is_zero())
+        .ok_or(ContractError::NothingEscrowed {})?;
+    if escrow.funder != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if IBC_CHANNELS.has(deps.storage, &channel_id) {
+        return Err(ContractError::ChannelOpen {});
+    }
+    REMOTE_ESCROWS.remove(deps.storage, key);
+    unreserve_funds(deps.storage, escrow.amount)?;
+
+    let (messages, queued) = pay_out(deps.branch(), &env, &escrow.funder, escrow.amount)?;
+    let event = RefundRemoteEscrowEvent {
+        channel_id,
+        remote_sender,
+        funder: escrow.funder,
+        amount: escrow.amount,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let mut res =
+        event_response(deps.storage, "refund_remote_escrow", event.into(), legacy_attributes)?
+            .add_messages(messages);
+    if queued {
+        res = res.add_attribute("queued", "true");
+    }
+    Ok(res)
+}
+
+/// Owner only. Channels that are already open are kept.
+pub fn execute_set_ibc_counterparty(
+    deps: DepsMut,
+    info: MessageInfo,
+    port_id: String,
+) -> Result<Response, ContractError> {
+    if info.sender != OWNER.load(deps.storage)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    IBC_COUNTERPARTY_PORT.save(deps.storage, &port_id)?;
+
+    let event = SetIbcCounterpartyEvent { port_id };
+    let legacy_attributes = event.legacy_attributes();
+    Ok(event_response(deps.storage, "set_ibc_counterparty", event.into(), legacy_attributes)?)
+}
+
+/// Runs a split packet from the counterparty. The split itself runs as a
+/// `ReceiveSplit` submessage, so whatever it wrote is reverted if any part of
+/// it fails, and `reply` turns the outcome into the acknowledgement instead of
+/// aborting the packet.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_receive(
+    deps: DepsMut,
+    env: Env,
+    msg: IbcPacketReceiveMsg,
+) -> Result<IbcReceiveResponse, Never> {
+    match dispatch_split(deps.as_ref(), env, &msg.packet) {
+        Ok(res) => Ok(res),
+        Err(err) => Ok(IbcReceiveResponse::new()
+            .set_ack(ack_binary(IbcAck::Error(err.to_string())))
+            .add_attribute("action", "receive_split")
+            .add_attribute("error", err.to_string())),
+    }
+}
+
+fn ack_binary(ack: IbcAck) -> Binary {
+    to_json_binary(&ack).expect("acknowledgement serializes")
+}
+
+fn dispatch_split(
+    deps: Deps,
+    env: Env,
+    packet: &IbcPacket,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let channel_id = packet.dest.channel_id.clone();
+    if !IBC_CHANNELS.has(deps.storage, &channel_id) {
+        return Err(ContractError::UnknownChannel {});
+    }
+    let packet: SplitPacket = from_json(&packet.data)?;
+    let message = WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_json_binary(&ExecuteMsg::ReceiveSplit { channel_id, packet })?,
+        funds: vec![],
+    };
+    // `reply` always replaces this with the split's own outcome
+    let ack = IbcAck::Error("split did not run".to_owned());
+    Ok(IbcReceiveResponse::new()
+        .set_ack(ack_binary(ack))
+        .add_attribute("action", "receive_split")
+        .add_submessage(SubMsg::reply_always(message, RECEIVE_SPLIT_REPLY_ID)))
+}
+
+/// Acknowledges a split packet with the transfer id its split returned, or
+/// with the error it failed on.
+fn reply_receive_split(msg: Reply) -> Result<Response, ContractError> {
+    let ack = match msg.result {
+        SubMsgResult::Ok(_) => {
+            let transfer_id = parse_reply_execute_data(msg)
+                .map_err(|err| StdError::generic_err(err.to_string()))?
+                .data
+                .unwrap_or_default();
+            IbcAck::Result(transfer_id)
+        }
+        SubMsgResult::Err(err) => IbcAck::Error(err),
+    };
+    Ok(Response::new().set_data(ack_binary(ack)))
+}
+
+pub fn execute_receive_split(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    split: SplitPacket,
+) -> Result<Response, ContractError> {
+    if info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized {});
+    }
+    let key = (channel_id.as_str(), split.sender.as_str());
+    let mut escrow = REMOTE_ESCROWS
+        .may_load(deps.storage, key)?
+        .ok_or(ContractError::NotEnoughEscrow {
+            escrowed: Uint128::zero(),
+        })?;
+    if escrow.amount < split.amount {
+        return Err(ContractError::NotEnoughEscrow {
+            escrowed: escrow.amount,
+        });
+    }
+    let fee = FEE.load(deps.storage)?;
+    let recipients = [split.recipient_1, split.recipient_2];
+    let plan = plan_transfer(deps.as_ref(), split.amount, fee, None, &recipients)?;
+
+    let sender = Addr::unchecked(&split.sender);
+    let mut forwards = vec![];
+    let event = apply_transfer(
+        deps.storage,
+        &env,
+        &sender,
+        &plan,
+        TransferTerms::default(),
+        &mut forwards,
+    )?;
+    // Dust the split leaves over stays in the escrow
+    escrow.amount -= plan.sender_charged;
+    REMOTE_ESCROWS.save(deps.storage, key, &escrow)?;
+    unreserve_funds(deps.storage, plan.sender_charged)?;
+
+    let transfer_id = to_json_binary(&event.transfer_id)?;
+    let res = Response::new()
+        .set_data(transfer_id)
+        .add_attribute("action", "receive_split")
+        .add_event(event.into());
+    let (messages, events) = pay_forwards(deps.branch(), &env, forwards)?;
+    Ok(res.add_messages(messages).add_events(events))
+}
+
+/// Sends `amount` to `to`. While staking, a payout the liquid funds cannot
+/// cover is queued for `ClaimWithdrawal` and the shortfall is unbonded.
+fn pay_out(
+    mut deps: DepsMut,
+    env: &Env,
+    to: &Addr,
+    amount: Uint128,
+) -> Result<(Vec<CosmosMsg>, bool), ContractError> {
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let message = BankMsg::Send {
+        to_address: to.to_string(),
+        amount: coins(amount.u128(), &coin_denom),
+    };
+    let config = STAKING.may_load(deps.storage)?;
+    // With staking off everything is held again once the last undelegation
+    // has come back
+    let unbonding = UNBONDING_UNTIL
+        .may_load(deps.storage)?
+        .is_some_and(|until| !until.is_expired(&env.block));
+    if config.is_none() && !unbonding {
+        return Ok((vec![message.into()], false));
+    }
+    let liquid = liquid_balance(deps.as_ref(), env)?;
+    if amount <= liquid {
+        return Ok((vec![message.into()], false));
+    }
+
+    queue_withdrawal(deps.storage, env, to, amount)?;
+    let config = match config {
+        Some(config) => config,
+        None => return Ok((vec![], true)),
+    };
+
+    // Unbond enough to cover the shortfall and restore the buffer
+    let delegated = DELEGATED.may_load(deps.storage)?.unwrap_or_default();
+    let unbond = (amount - liquid + config.buffer).min(delegated);
+    let mut messages = vec![];
+    if !unbond.is_zero() {
+        book_rewards(deps.branch(), env, &config.validator)?;
+        DELEGATED.save(deps.storage, &(delegated - unbond))?;
+        messages.push(
+            StakingMsg::Undelegate {
+                validator: config.validator,
+                amount: coin(unbond.u128(), &coin_denom),
+            }
+            .into(),
+        );
+    }
+    Ok((messages, true))
+}
+
+/// Adds a withdrawal to the back of the queue, to be claimed once the
+/// unbonding period has passed.
+fn queue_withdrawal(
+    storage: &mut dyn Storage,
+    env: &Env,
+    to: &Addr,
+    amount: Uint128,
+) -> StdResult<()> {
+    let id = WITHDRAWAL_QUEUE_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    WITHDRAWAL_QUEUE_COUNT.save(storage, &id)?;
+    let unbonding_period = UNBONDING_PERIOD.may_load(storage)?.unwrap_or(DEFAULT_UNBONDING_PERIOD);
+    let queued = QueuedWithdrawal {
+        recipient: to.clone(),
+        amount,
+        matures: unbonding_period.after(&env.block),
+    };
+    WITHDRAWAL_QUEUE.save(storage, id, &queued)?;
+
+    QUEUED_WITHDRAWALS.update(storage, to, |queued| -> StdResult<_> {
+        Ok(queued.unwrap_or_default().checked_add(amount)?)
+    })?;
+    let queued_total = QUEUED_TOTAL.may_load(storage)?.unwrap_or_default();
+    QUEUED_TOTAL.save(storage, &queued_total.checked_add(amount)?)
+}
+
+/// Pays out the sender's matured queued withdrawals. The queue is settled
+/// in order, so unbonded funds go to earlier entries first, and reserved
+/// funds are never used.
+pub fn execute_claim_withdrawal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if !QUEUED_WITHDRAWALS.has(deps.storage, &info.sender) {
+        return Err(ContractError::NothingQueued {});
+    }
+
+    // Everything matured up to the sender's last entry must be covered
+    let mut owed = Uint128::zero();
+    let mut needed = Uint128::zero();
+    let mut amount = Uint128::zero();
+    let mut claimed = vec![];
+    for item in WITHDRAWAL_QUEUE.range(deps.storage, None, None, Order::Ascending) {
+        let (id, queued) = item?;
+        if !queued.matures.is_expired(&env.block) {
+            break;
+        }
+        owed += queued.amount;
+        if queued.recipient == info.sender {
+            amount += queued.amount;
+            needed = owed;
+            claimed.push(id);
+        }
+    }
+    let coin_denom = COIN_DENOM.load(deps.storage)?;
+    let held = deps.querier.query_balance(&env.contract.address, &coin_denom)?.amount;
+    let reserved = RESERVED.may_load(deps.storage)?.unwrap_or_default();
+    if amount.is_zero() || held.saturating_sub(reserved) < needed {
+        return Err(ContractError::StillUnbonding {});
+    }
+
+    for id in claimed {
+        WITHDRAWAL_QUEUE.remove(deps.storage, id);
+    }
+    let queued = QUEUED_WITHDRAWALS.load(deps.storage, &info.sender)?;
+    if queued == amount {
+        QUEUED_WITHDRAWALS.remove(deps.storage, &info.sender);
+    } else {
+        QUEUED_WITHDRAWALS.save(deps.storage, &info.sender, &(queued - amount))?;
+    }
+    QUEUED_TOTAL.update(deps.storage, |queued| -> StdResult<_> { Ok(queued - amount) })?;
+
+    let send = BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: coins(amount.u128(), &coin_denom),
+    };
+    let event = ClaimWithdrawalEvent {
+        recipient: info.sender,
+        amount,
+    };
+    let legacy_attributes = event.legacy_attributes();
+    let res = event_response(deps.storage, "claim_withdrawal", event.into(), legacy_attributes)?;
+    Ok(res.add_message(send))
+}
+
+/// Seconds of `stream` that have run by `now`.
+fn streamed_seconds(stream: &Stream, now: Timestamp) -> u64 {
+    now.min(stream.end).seconds().saturating_sub(stream.start.seconds())
+}
+
+/// A stream's id, what it has streamed to a recipient so far and whether it
+/// has ended.
+type StreamAccrual = (u64, Uint128, bool);
+
+/// Everything streamed to `recipient` by `now`, per stream, along with how
+/// much of it has not yet been moved into their balance.
+fn stream_accruals(
+    storage: &dyn Storage,
+    recipient: &Addr,
+    now: Timestamp,
+) -> StdResult<(Vec<StreamAccrual>, Uint128)> {
+    let mut streams = vec![];
+    let mut unreleased = Uint128::zero();
+    for item in STREAMED.prefix(recipient).range(storage, None, None, Order::Ascending) {
+        let (stream_id, released) = item?;
+        let stream = STREAMS.load(storage, stream_id)?;
+        let shares = stream.recipients.iter().filter(|r| *r == recipient).count() as u64;
+        let streamed = stream.rate_per_second
+            * Uint128::from(shares)
+            * Uint128::from(streamed_seconds(&stream, now));
+        unreleased += streamed - released;
+        streams.push((stream_id, streamed, now >= stream.end));
+    }
+    Ok((streams, unreleased))
+}
+
+pub fn add_balance(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    amount: Uint128,
+    height: u64,
+) -> StdResult<()> {
+    let balance = BALANCE.may_load(storage, address)?.unwrap_or_default();
+    settle_rewards(storage, address, balance)?;
+    BALANCE.save(storage, address, &(balance + amount), height)?;
+    let total = TOTAL_BALANCE.may_load(storage)?.unwrap_or_default();
+    TOTAL_BALANCE.save(storage, &(total + amount))?;
+    Ok(())
+}
+
+pub fn sub_balance(
+    storage: &mut dyn Storage,
+    address: &Addr,
+    amount: Uint128,
+    height: u64,
+) -> Result<(), ContractError> {
+    let balance = BALANCE.may_load(storage, address)?.unwrap_or_default();
+    let remaining = balance
+        .checked_sub(amount)
+        .map_err(|_| ContractError::NotEnoughBalance {})?;
+    settle_rewards(storage, address, balance)?;
+    BALANCE.save(storage, address, &remaining, height)?;
+    let total = TOTAL_BALANCE.may_load(storage)?.unwrap_or_default();
+    TOTAL_BALANCE.save(storage, &total.saturating_sub(amount))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_dependencies_with_balance, mock_env, mock_ibc_channel_close_init,
+        mock_ibc_channel_connect_ack, mock_ibc_channel_connect_confirm, mock_ibc_channel_open_init,
+        mock_ibc_channel_open_try, mock_ibc_packet_recv, mock_info, MockApi, MockQuerier,
+        MockStorage, MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{
+        Api, BlockInfo, ContractInfoResponse, ContractResult, CustomQuery, DelegationResponse,
+        FullDelegation, OverflowError, OverflowOperation, OwnedDeps, Querier, ReplyOn, StakingQuery, SubMsgResponse, SystemError,
+        SystemResult, WasmQuery,
+    };
+    use cw_multi_test::{
+        AppBuilder, AppResponse, ContractWrapper, CosmosRouter, Executor, Module, Staking,
+        StakingSudo,
+    };
+    use schemars::JsonSchema;
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+
+    fn instantiate_msg(fee: u128) -> InstantiateMsg {
+        InstantiateMsg {
+            coin_denom: "sei".to_owned(),
+            owner: "owner".to_owned(),
+            fee: Uint128::new(fee),
+            legacy_attributes: None,
+            batch_fee_mode: None,
+            internal_fee_mode: None,
+            cancel_fee_mode: None,
+            keeper_bounty: None,
+            hook_failure_mode: None,
+            unbonding_period: None,
+            ibc_counterparty_port: Some("their_port".to_owned()),
+        }
+    }
+
+    /// Looks up an attribute by key rather than by position.
+    fn attr<'a>(attributes: &'a [Attribute], key: &str) -> &'a str {
+        attributes
+            .iter()
+            .find(|a| a.key == key)
+            .unwrap_or_else(|| panic!("missing attribute {}", key))
+            .value
+            .as_str()
+    }
+
+    fn event_attr<'a>(res: &'a Response, ty: &str, key: &str) -> &'a str {
+        let event = res
+            .events
+            .iter()
+            .find(|e| e.ty == ty)
+            .unwrap_or_else(|| panic!("missing event {}", ty));
+        attr(&event.attributes, key)
+    }
+
+    #[test]
+    fn test_instantiate() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+    }
+
+    #[test]
+    fn test_query_owner_address() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
 
         // query owner address
         let query_msg = QueryMsg::Owner {};
         let owner_resp: OwnerResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
         assert_eq!("owner", owner_resp.owner);
     }
 
     #[test]
-    fn test_query_fee() {
+    fn test_query_fee() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // query owner address
+        let query_msg = QueryMsg::Fee {};
+        let fee_resp: FeeResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!("1", fee_resp.fee);
+    }
+
+    #[test]
+    fn test_query_owner_balance() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // The owner should have 0sei
+        let owner = "owner".into();
+        let query_msg = QueryMsg::Balance {
+            address: owner,
+            height: None,
+        };
+        let balance_resp: BalanceResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(0), balance_resp.balance);
+    }
+    #[test]
+    fn test_transfer_even_amount() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // send 100sei, owner gets 2, recipients get 49sei. None left for the sender
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let recipient_1 = "recipient_1".into();
+        let recipient_2 = "recipient_2".into();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("transfer", attr(&exec_res.attributes, "action"));
+        assert_eq!("recipient_1", event_attr(&exec_res, "split_transfer", "recipient_1"));
+        assert_eq!("49", event_attr(&exec_res, "split_transfer", "recipient_1_received"));
+        assert_eq!("2", event_attr(&exec_res, "split_transfer", "owner_received"));
+        assert_eq!("100", event_attr(&exec_res, "split_transfer", "sender_charged"));
+    }
+
+
+    #[test]
+    fn test_transfer_odd_amount() {
+        // Instantiate the contract
+        let fee = Uint128::new(2);
+        let instantiate_msg = instantiate_msg(fee.u128());
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // send 99sei, owner gets 2, recipients get 48sei. 1sei left for the sender
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let recipient_1 = "recipient_1".into();
+        let recipient_2 = "recipient_2".into();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(99),
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("transfer", attr(&exec_res.attributes, "action"));
+        assert_eq!("recipient_1", event_attr(&exec_res, "split_transfer", "recipient_1"));
+        assert_eq!("48", event_attr(&exec_res, "split_transfer", "recipient_1_received"));
+        assert_eq!(fee.to_string(), event_attr(&exec_res, "split_transfer", "owner_received"));
+        assert_eq!("98", event_attr(&exec_res, "split_transfer", "sender_charged"));
+    }
+
+    #[test]
+    fn test_transfer_fee_plus_1_error() {
+        // Instantiate the contract
+        let fee = Uint128::new(2);
+        let instantiate_msg = instantiate_msg(fee.u128());
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // send 3sei, owner gets 2, recipients get 1sei and 0sei?. Should throw RecipientPaidZeroOrOneCoin error
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let recipient_1 = "recipient_1".into();
+        let recipient_2 = "recipient_2".into();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(3),
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
+        assert_eq!(ContractError::RecipientPaidZeroOrOneCoin {}, exec_res);
+
+    }
+
+    #[test]
+    fn test_transfer_fee_error() {
+        // Instantiate the contract
+        let fee = Uint128::new(2);
+        let instantiate_msg = instantiate_msg(fee.u128());
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // send 2sei, owner gets 2, recipients get 0sei and 0sei?. Should throw RecipientPaidZeroOrOneCoin error
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let recipient_1 = "recipient_1".into();
+        let recipient_2 = "recipient_2".into();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(3),
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
+        assert_eq!(ContractError::RecipientPaidZeroOrOneCoin {}, exec_res);
+
+    }
+
+    #[test]
+    fn test_query_zero_balance() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // Users with no balance should be given a zero balance.
+        let recipient_1 = "no_bal_user".into();
+        let query_msg = QueryMsg::Balance {
+            address: recipient_1,
+            height: None,
+        };
+        let balance_resp: BalanceResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(0), balance_resp.balance);
+    }
+
+    #[test]
+    fn test_query_nonzero_balance() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // transfer 100sei with a 1sei fee.  Recipients should get 49sei, and owner should get 1. Overall, the sender is deducted 99sei.
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let recipient_1 = "recipient_1".into();
+        let recipient_2 = "recipient_2".into();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res: Response = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("transfer", attr(&exec_res.attributes, "action"));
+        assert_eq!("recipient_1", event_attr(&exec_res, "split_transfer", "recipient_1"));
+        assert_eq!("49", event_attr(&exec_res, "split_transfer", "recipient_1_received"));
+        assert_eq!("1", event_attr(&exec_res, "split_transfer", "owner_received"));
+
+        // Each recipient should now have 49sei
+        let recipient_1 = "recipient_1".into();
+        let query_msg = QueryMsg::Balance {
+            address: recipient_1,
+            height: None,
+        };
+        let balance_resp: BalanceResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(49), balance_resp.balance);
+    }
+
+    #[test]
+    fn test_withdraw_nonzero_amount() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // send 100sei, owner gets 1, recipients get 49sei. 1sei left for the sender
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let recipient_1 = "recipient_1".into();
+        let recipient_2 = "recipient_2".into();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res: Response = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("transfer", attr(&exec_res.attributes, "action"));
+        assert_eq!("recipient_1", event_attr(&exec_res, "split_transfer", "recipient_1"));
+        assert_eq!("49", event_attr(&exec_res, "split_transfer", "recipient_1_received"));
+        assert_eq!("1", event_attr(&exec_res, "split_transfer", "owner_received"));
+
+        // Each recipient should now have 49sei
+        let recipient_1 = "recipient_1".into();
+        let query_msg = QueryMsg::Balance {
+            address: recipient_1,
+            height: None,
+        };
+        let balance_resp: BalanceResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(49), balance_resp.balance);
+
+        // The recpient should be able to withdraw the 49sei
+        let info_recip = mock_info(&String::from("recipient_1"), &balance);
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(49),
+        };
+        let exec_res: Response = execute(deps.as_mut(), mock_env(), info_recip, exec_msg).unwrap();
+        assert_eq!("withdraw", attr(&exec_res.attributes, "action"));
+        assert_eq!("recipient_1", event_attr(&exec_res, "withdraw", "sender"));
+        assert_eq!("49", event_attr(&exec_res, "withdraw", "amount"));
+
+        // recipient_1 should now have 0sei
+        let recipient_1 = "recipient_1".into();
+        let query_msg = QueryMsg::Balance {
+            address: recipient_1,
+            height: None,
+        };
+        let balance_resp: BalanceResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(0), balance_resp.balance);
+
+        // recipient_2 should still have 49sei
+        let recipient_1 = "recipient_2".into();
+        let query_msg = QueryMsg::Balance {
+            address: recipient_1,
+            height: None,
+        };
+        let balance_resp: BalanceResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(49), balance_resp.balance);
+    }
+
+    #[test]
+    fn test_legacy_attributes() {
+        // Instantiate the contract with legacy attributes switched on
+        let instantiate_msg = InstantiateMsg {
+            legacy_attributes: Some(true),
+            ..instantiate_msg(1)
+        };
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // The old flat attributes are emitted alongside the typed event
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "recipient_1".into(),
+            recipient_2: "recipient_2".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!(("action", "transfer"), exec_res.attributes[0]);
+        assert_eq!(("recipient_1_recieved", "49"), exec_res.attributes[4]);
+        assert_eq!(("owner_recieved", "1"), exec_res.attributes[6]);
+        assert_eq!("49", event_attr(&exec_res, "split_transfer", "recipient_1_received"));
+
+        let info_recip = mock_info(&String::from("recipient_1"), &[]);
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(49),
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), info_recip, exec_msg).unwrap();
+        assert_eq!(("withdraw_amount", "49"), exec_res.attributes[2]);
+        assert_eq!("49", event_attr(&exec_res, "withdraw", "amount"));
+
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "revenue".into(),
+            recipients: vec![split_recipient("alice", 1)],
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        assert_eq!(("split_id", "1"), exec_res.attributes[1]);
+        assert_eq!("revenue", event_attr(&exec_res, "split_created", "name"));
+    }
+
+    #[test]
+    fn test_transfer_history() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // alice appears as recipient_1, recipient_2 and sender across three transfers
+        let transfers = [
+            ("some_user", "alice", "bob"),
+            ("some_user", "carol", "alice"),
+            ("alice", "bob", "carol"),
+        ];
+        for (sender, recipient_1, recipient_2) in transfers {
+            let info = mock_info(sender, &balance);
+            let exec_msg = ExecuteMsg::Transfer {
+                transfer_amount: Uint128::new(100),
+                recipient_1: recipient_1.into(),
+                recipient_2: recipient_2.into(),
+                claim_deadline: None,
+                cancel_window: None,
+                vesting: None,
+                msg: None,
+            };
+            execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let query_msg = QueryMsg::TransfersBySender {
+            address: "some_user".into(),
+            start_after: None,
+            limit: None,
+        };
+        let resp: TransfersResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        let ids: Vec<u64> = resp.transfers.iter().map(|t| t.id).collect();
+        assert_eq!(vec![1, 2], ids);
+        assert_eq!(Uint128::new(49), resp.transfers[0].recipient_amount);
+        assert_eq!(mock_env().block.height, resp.transfers[0].block_height);
+
+        // Recipient lookups merge both recipient slots
+        let query_msg = QueryMsg::TransfersByRecipient {
+            address: "alice".into(),
+            start_after: None,
+            limit: None,
+        };
+        let resp: TransfersResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        let ids: Vec<u64> = resp.transfers.iter().map(|t| t.id).collect();
+        assert_eq!(vec![1, 2], ids);
+
+        // and paginate by transfer id
+        let query_msg = QueryMsg::TransfersByRecipient {
+            address: "carol".into(),
+            start_after: Some(2),
+            limit: Some(1),
+        };
+        let resp: TransfersResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(1, resp.transfers.len());
+        assert_eq!(3, resp.transfers[0].id);
+        assert_eq!("alice", resp.transfers[0].sender.as_str());
+    }
+
+    #[test]
+    fn test_query_balance_at_height() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let balance = coins(100, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // recipient_1 is credited 49sei in one block and withdraws 20sei in the next
+        let transfer_env = mock_env();
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "recipient_1".into(),
+            recipient_2: "recipient_2".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), transfer_env.clone(), info2, exec_msg).unwrap();
+
+        let mut withdraw_env = mock_env();
+        withdraw_env.block.height = transfer_env.block.height + 1;
+        let info_recip = mock_info(&String::from("recipient_1"), &[]);
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(20),
+        };
+        execute(deps.as_mut(), withdraw_env.clone(), info_recip, exec_msg).unwrap();
+
+        let balance_at = |height: Option<u64>| -> Uint128 {
+            let query_msg = QueryMsg::Balance {
+                address: "recipient_1".into(),
+                height,
+            };
+            let balance_resp: BalanceResp =
+                from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            balance_resp.balance
+        };
+        assert_eq!(Uint128::new(0), balance_at(Some(transfer_env.block.height)));
+        assert_eq!(Uint128::new(49), balance_at(Some(withdraw_env.block.height)));
+        assert_eq!(Uint128::new(29), balance_at(Some(withdraw_env.block.height + 1)));
+        assert_eq!(Uint128::new(29), balance_at(None));
+    }
+
+    #[test]
+    fn test_simulate_transfer() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let balance = coins(99, "sei");
+        let info = mock_info(&String::from("some_user"), &balance);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // 99sei with a 2sei fee: recipients get 48sei each and 1sei is refunded
+        let query_msg = QueryMsg::SimulateTransfer {
+            transfer_amount: Uint128::new(99),
+            recipients: vec!["recipient_1".into(), "recipient_2".into()],
+        };
+        let sim: SimulateTransferResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(2), sim.fee);
+        assert_eq!(
+            vec![
+                RecipientAmount {
+                    address: "recipient_1".into(),
+                    amount: Uint128::new(48)
+                },
+                RecipientAmount {
+                    address: "recipient_2".into(),
+                    amount: Uint128::new(48)
+                },
+            ],
+            sim.recipients
+        );
+        assert_eq!(Uint128::new(98), sim.sender_charged);
+        assert_eq!(Uint128::new(1), sim.refund);
+
+        // The real transfer agrees, and sends the refund back
+        let info2 = mock_info(&String::from("some_user"), &balance);
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(99),
+            recipient_1: "recipient_1".into(),
+            recipient_2: "recipient_2".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("48", event_attr(&exec_res, "split_transfer", "recipient_1_received"));
+        assert_eq!("1", event_attr(&exec_res, "split_transfer", "refund"));
+        assert_eq!(
+            exec_res.messages[1].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(1, "sei"),
+            }
+            .into()
+        );
+
+        // Failing transfers report the error the real call would return
+        let query_msg = QueryMsg::SimulateTransfer {
+            transfer_amount: Uint128::new(3),
+            recipients: vec!["recipient_1".into(), "recipient_2".into()],
+        };
+        let err = query(deps.as_ref(), mock_env(), query_msg).unwrap_err();
+        assert_eq!(
+            StdError::generic_err(ContractError::RecipientPaidZeroOrOneCoin {}.to_string()),
+            err
+        );
+        let query_msg = QueryMsg::SimulateTransfer {
+            transfer_amount: Uint128::new(99),
+            recipients: vec!["recipient_1".into()],
+        };
+        let err = query(deps.as_ref(), mock_env(), query_msg).unwrap_err();
+        assert_eq!(
+            StdError::generic_err(ContractError::InvalidRecipientCount {}.to_string()),
+            err
+        );
+    }
+
+    fn batch_item(amount: u128, recipient_1: &str, recipient_2: &str) -> BatchTransferItem {
+        BatchTransferItem {
+            amount: Uint128::new(amount),
+            recipient_1: recipient_1.into(),
+            recipient_2: recipient_2.into(),
+        }
+    }
+
+    fn query_balance_of(deps: Deps, address: &str) -> Uint128 {
+        let query_msg = QueryMsg::Balance {
+            address: address.into(),
+            height: None,
+        };
+        let balance_resp: BalanceResp =
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+        balance_resp.balance
+    }
+
+    #[test]
+    fn test_batch_transfer_fee_per_item() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // Two items of 10sei and 21sei, each paying the 2sei fee: 35sei in total
+        let exec_msg = ExecuteMsg::BatchTransfer {
+            transfers: vec![batch_item(10, "alice", "bob"), batch_item(21, "alice", "carol")],
+        };
+        let info2 = mock_info(&String::from("some_user"), &coins(35, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("batch_transfer", attr(&exec_res.attributes, "action"));
+        assert_eq!("4", event_attr(&exec_res, "batch_transfer", "total_fee"));
+        assert_eq!("1", event_attr(&exec_res, "batch_transfer", "refund"));
+        assert_eq!(2, exec_res.events.iter().filter(|e| e.ty == "split_transfer").count());
+
+        assert_eq!(Uint128::new(15), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(5), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(10), query_balance_of(deps.as_ref(), "carol"));
+        assert_eq!(Uint128::new(4), query_balance_of(deps.as_ref(), "owner"));
+    }
+
+    #[test]
+    fn test_batch_transfer_fee_per_batch() {
+        // Instantiate the contract charging the fee once per batch
+        let instantiate_msg = InstantiateMsg {
+            batch_fee_mode: Some(BatchFeeMode::PerBatch),
+            ..instantiate_msg(2)
+        };
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // 10sei + 20sei + a single 2sei fee
+        let exec_msg = ExecuteMsg::BatchTransfer {
+            transfers: vec![batch_item(10, "alice", "bob"), batch_item(20, "alice", "carol")],
+        };
+        let info2 = mock_info(&String::from("some_user"), &coins(32, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("2", event_attr(&exec_res, "batch_transfer", "total_fee"));
+        assert_eq!(Uint128::new(2), query_balance_of(deps.as_ref(), "owner"));
+        assert_eq!(Uint128::new(15), query_balance_of(deps.as_ref(), "alice"));
+    }
+
+    #[test]
+    fn test_batch_transfer_funds_mismatch_error() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // Attaching more than the amounts plus fees is rejected as a whole
+        let exec_msg = ExecuteMsg::BatchTransfer {
+            transfers: vec![batch_item(10, "alice", "bob"), batch_item(20, "alice", "carol")],
+        };
+        let info2 = mock_info(&String::from("some_user"), &coins(40, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg.clone()).unwrap_err();
+        assert_eq!(
+            ContractError::IncorrectFunds {
+                expected: Uint128::new(34),
+                received: Uint128::new(40)
+            },
+            exec_res
+        );
+
+        // A single invalid item fails the whole batch
+        let exec_msg = ExecuteMsg::BatchTransfer {
+            transfers: vec![batch_item(10, "alice", "bob"), batch_item(1, "alice", "carol")],
+        };
+        let info2 = mock_info(&String::from("some_user"), &coins(15, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
+        assert_eq!(ContractError::RecipientPaidZeroOrOneCoin {}, exec_res);
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+    }
+
+    fn split_recipient(address: &str, shares: u64) -> SplitRecipient {
+        SplitRecipient {
+            payee: Payee::Address(address.into()),
+            shares,
+        }
+    }
+
+    fn nested_split(split_id: u64, shares: u64) -> SplitRecipient {
+        SplitRecipient {
+            payee: Payee::Split(split_id),
+            shares,
+        }
+    }
+
+    #[test]
+    fn test_pay_into_split() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // alice registers a 3:1 split between herself and bob
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "revenue".into(),
+            recipients: vec![split_recipient("alice", 3), split_recipient("bob", 1)],
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        assert_eq!("1", event_attr(&exec_res, "split_created", "split_id"));
+
+        // 42sei less the 1sei fee: alice gets 30, bob 10 and 1sei of dust is refunded
+        let exec_msg = ExecuteMsg::PayIntoSplit { split_id: 1 };
+        let info2 = mock_info("payer", &coins(42, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("alice", event_attr(&exec_res, "split_payment", "recipient_1"));
+        assert_eq!("30", event_attr(&exec_res, "split_payment", "recipient_1_received"));
+        assert_eq!("10", event_attr(&exec_res, "split_payment", "recipient_2_received"));
+        assert_eq!("1", event_attr(&exec_res, "split_payment", "refund"));
+        assert_eq!(Uint128::new(30), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(10), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(1), query_balance_of(deps.as_ref(), "owner"));
+
+        // Paying into a split that does not exist fails
+        let exec_msg = ExecuteMsg::PayIntoSplit { split_id: 2 };
+        let info2 = mock_info("payer", &coins(42, "sei"));
+        execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
+    }
+
+    #[test]
+    fn test_update_and_lock_split() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "team".into(),
+            recipients: vec![split_recipient("alice", 1), split_recipient("bob", 1)],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        // Only the split owner can update it
+        let update_msg = ExecuteMsg::UpdateSplit {
+            split_id: 1,
+            name: None,
+            recipients: vec![split_recipient("bob", 1)],
+        };
+        let exec_res =
+            execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), update_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, exec_res);
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), update_msg.clone()).unwrap();
+
+        let query_msg = QueryMsg::Splits {
+            owner: Some("alice".into()),
+            start_after: None,
+            limit: None,
+        };
+        let splits_resp: SplitsResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(1, splits_resp.splits.len());
+        assert_eq!(
+            SplitPayee::Address(Addr::unchecked("bob")),
+            splits_resp.splits[0].recipients[0].payee
+        );
+
+        // Once locked the split can no longer change
+        let exec_msg = ExecuteMsg::LockSplit { split_id: 1 };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        let exec_res =
+            execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), update_msg).unwrap_err();
+        assert_eq!(ContractError::SplitLocked {}, exec_res);
+
+        let query_msg = QueryMsg::Split { split_id: 1 };
+        let split: Split = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert!(split.locked);
+
+        // Zero shares are rejected
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "bad".into(),
+            recipients: vec![split_recipient("alice", 0)],
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::ZeroShares {}, exec_res);
+
+        // Shares that overflow a u64 when added up are rejected
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "bad".into(),
+            recipients: vec![split_recipient("alice", u64::MAX), split_recipient("bob", 1)],
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::SharesOverflow {}, exec_res);
+    }
+
+    #[test]
+    fn test_pay_into_nested_split() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // Split 1 is a team of carol and dave, which gets half of split 2
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "team".into(),
+            recipients: vec![split_recipient("carol", 1), split_recipient("dave", 1)],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "project".into(),
+            recipients: vec![split_recipient("alice", 1), nested_split(1, 1)],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        // 41sei less the 1sei fee: alice gets 20, carol and dave 10 each
+        let exec_msg = ExecuteMsg::PayIntoSplit { split_id: 2 };
+        let info2 = mock_info("payer", &coins(41, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("split:1", event_attr(&exec_res, "split_payment", "recipient_2"));
+        assert_eq!("20", event_attr(&exec_res, "split_payment", "recipient_2_received"));
+        assert_eq!("2", event_attr(&exec_res, "split_distribution", "parent_split_id"));
+        assert_eq!("1", event_attr(&exec_res, "split_distribution", "depth"));
+        assert_eq!("dave", event_attr(&exec_res, "split_distribution", "recipient_2"));
+        assert_eq!(Uint128::new(20), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(10), query_balance_of(deps.as_ref(), "carol"));
+        assert_eq!(Uint128::new(10), query_balance_of(deps.as_ref(), "dave"));
+
+        // Split 1 can not be pointed back at split 2, or at itself
+        for split_id in [2, 1] {
+            let exec_msg = ExecuteMsg::UpdateSplit {
+                split_id: 1,
+                name: None,
+                recipients: vec![split_recipient("carol", 1), nested_split(split_id, 1)],
+            };
+            let exec_res =
+                execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), exec_msg).unwrap_err();
+            assert_eq!(ContractError::SplitCycle {}, exec_res);
+        }
+    }
+
+    #[test]
+    fn test_nested_split_depth_limit() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "leaf".into(),
+            recipients: vec![split_recipient("alice", 1)],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        // Each new split nests the previous one, up to the limit
+        for split_id in 1..=MAX_SPLIT_DEPTH as u64 {
+            let exec_msg = ExecuteMsg::CreateSplit {
+                name: "chain".into(),
+                recipients: vec![nested_split(split_id, 1)],
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        }
+        let exec_msg = ExecuteMsg::CreateSplit {
+            name: "chain".into(),
+            recipients: vec![nested_split(MAX_SPLIT_DEPTH as u64 + 1, 1)],
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::SplitTooDeep { max: MAX_SPLIT_DEPTH }, exec_res);
+    }
+
+    #[test]
+    fn test_internal_transfer() {
+        // Instantiate the contract with the fee charged on top of internal moves
+        let instantiate_msg = InstantiateMsg {
+            internal_fee_mode: Some(InternalFeeMode::OnTop),
+            ..instantiate_msg(2)
+        };
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        let height = mock_env().block.height;
+        add_balance(deps.as_mut().storage, &Addr::unchecked("alice"), Uint128::new(50), height).unwrap();
+
+        // alice passes 20sei to bob, paying the 2sei fee on top
+        let exec_msg = ExecuteMsg::InternalTransfer {
+            to: "bob".into(),
+            amount: Uint128::new(20),
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        assert_eq!(0, exec_res.messages.len());
+        assert_eq!("22", event_attr(&exec_res, "internal_transfer", "debited"));
+        assert_eq!("20", event_attr(&exec_res, "internal_transfer", "credited"));
+        assert_eq!(Uint128::new(28), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(20), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(2), query_balance_of(deps.as_ref(), "owner"));
+
+        // She can not move more than she has left
+        let exec_msg = ExecuteMsg::InternalTransfer {
+            to: "bob".into(),
+            amount: Uint128::new(27),
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::NotEnoughBalance {}, exec_res);
+    }
+
+    #[test]
+    fn test_internal_split() {
+        // Instantiate the contract with the fee deducted from internal moves
+        let instantiate_msg = InstantiateMsg {
+            internal_fee_mode: Some(InternalFeeMode::Deducted),
+            ..instantiate_msg(1)
+        };
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        let height = mock_env().block.height;
+        add_balance(deps.as_mut().storage, &Addr::unchecked("alice"), Uint128::new(50), height).unwrap();
+
+        // 50sei less the 1sei fee is split 24/24; the odd coin stays with alice
+        let exec_msg = ExecuteMsg::InternalSplit {
+            amount: Uint128::new(50),
+            recipient_1: "bob".into(),
+            recipient_2: "carol".into(),
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        assert_eq!("internal_split", attr(&exec_res.attributes, "action"));
+        assert_eq!("24", event_attr(&exec_res, "split_transfer", "recipient_2_received"));
+        assert_eq!(Uint128::new(1), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(24), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(24), query_balance_of(deps.as_ref(), "carol"));
+        assert_eq!(Uint128::new(1), query_balance_of(deps.as_ref(), "owner"));
+    }
+
+    #[test]
+    fn test_withdraw_from_allowance() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+        let height = mock_env().block.height;
+        add_balance(deps.as_mut().storage, &Addr::unchecked("alice"), Uint128::new(50), height).unwrap();
+
+        // alice lets the bot sweep up to 30sei until the next block
+        let exec_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "bot".into(),
+            amount: Uint128::new(30),
+            expires: Some(Expiration::AtHeight(height + 1)),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::WithdrawFrom {
+            owner: "alice".into(),
+            amount: Uint128::new(20),
+            recipient: "vault".into(),
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), exec_msg).unwrap();
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "vault".into(),
+                amount: coins(20, "sei"),
+            }
+            .into()
+        );
+        assert_eq!("bot", event_attr(&exec_res, "withdraw_from", "spender"));
+        assert_eq!(Uint128::new(30), query_balance_of(deps.as_ref(), "alice"));
+
+        let query_msg = QueryMsg::Allowance {
+            owner: "alice".into(),
+            spender: "bot".into(),
+        };
+        let allowance: AllowanceResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(10), allowance.allowance);
+
+        // The bot can not go over what is left of its allowance
+        let exec_msg = ExecuteMsg::WithdrawFrom {
+            owner: "alice".into(),
+            amount: Uint128::new(11),
+            recipient: "vault".into(),
+        };
+        let exec_res =
+            execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), exec_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::NoAllowance {}, exec_res);
+
+        // and once it expires nothing more can be withdrawn
+        let mut env = mock_env();
+        env.block.height = height + 1;
+        let exec_res = execute(deps.as_mut(), env, mock_info("bot", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::Expired {}, exec_res);
+    }
+
+    #[test]
+    fn test_decrease_allowance() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(1);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        for spender in ["bot", "keeper"] {
+            let exec_msg = ExecuteMsg::IncreaseAllowance {
+                spender: spender.into(),
+                amount: Uint128::new(30),
+                expires: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        }
+
+        // Decreasing by the whole allowance removes it
+        let exec_msg = ExecuteMsg::DecreaseAllowance {
+            spender: "bot".into(),
+            amount: Uint128::new(30),
+            expires: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        let query_msg = QueryMsg::AllAllowances {
+            owner: "alice".into(),
+            start_after: None,
+            limit: None,
+        };
+        let resp: AllAllowancesResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(1, resp.allowances.len());
+        assert_eq!("keeper", resp.allowances[0].spender);
+
+        // Nobody can grant themselves an allowance
+        let exec_msg = ExecuteMsg::IncreaseAllowance {
+            spender: "alice".into(),
+            amount: Uint128::new(30),
+            expires: None,
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::CannotSetOwnAccount {}, exec_res);
+    }
+
+    #[test]
+    fn test_transfer_forwarding() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // The exchange forwards everything to its hot wallet
+        let exec_msg = ExecuteMsg::SetForwarding {
+            preference: Some(ForwardPreference::ForwardTo {
+                address: "hot_wallet".into(),
+            }),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("exchange", &[]), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "exchange".into(),
+            recipient_2: "recipient_2".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert!(exec_res.messages.iter().any(|m| m.msg
+            == BankMsg::Send {
+                to_address: "hot_wallet".into(),
+                amount: coins(49, "sei"),
+            }
+            .into()));
+        assert_eq!("hot_wallet", event_attr(&exec_res, "forward", "to"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "exchange"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "recipient_2"));
+
+        // Clearing the preference goes back to crediting the balance
+        let exec_msg = ExecuteMsg::SetForwarding { preference: None };
+        execute(deps.as_mut(), mock_env(), mock_info("exchange", &[]), exec_msg).unwrap();
+        let query_msg = QueryMsg::Forwarding {
+            address: "exchange".into(),
+        };
+        let resp: ForwardingResp =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(None, resp.forwarding);
+    }
+
+    #[test]
+    fn test_forwarding_threshold() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // alice accumulates until she holds at least 90sei
+        let exec_msg = ExecuteMsg::SetForwarding {
+            preference: Some(ForwardPreference::Threshold {
+                amount: Uint128::new(90),
+            }),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2.clone(), exec_msg.clone()).unwrap();
+        assert_eq!(1, exec_res.messages.len());
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "alice"));
+
+        // The second credit takes her to 98sei, which is all paid out
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("98", event_attr(&exec_res, "forward", "amount"));
+        assert_eq!("alice", event_attr(&exec_res, "forward", "to"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(98), query_balance_of(deps.as_ref(), "bob"));
+    }
+
+    #[test]
+    fn test_claim_before_deadline() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let env = mock_env();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: Some(Expiration::AtHeight(env.block.height + 10)),
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(2), query_balance_of(deps.as_ref(), "owner"));
+
+        let query_msg = QueryMsg::PendingClaims {
+            recipient: "alice".into(),
+            start_after: None,
+            limit: None,
+        };
+        let resp: ClaimsResp =
+            from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(1, resp.claims.len());
+        assert_eq!(Uint128::new(49), resp.claims[0].amount);
+
+        // alice claims in time; bob's share is still waiting
+        let exec_msg = ExecuteMsg::Claim { transfer_id: 1 };
+        let exec_res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            exec_msg.clone(),
+        )
+        .unwrap();
+        assert_eq!("49", event_attr(&exec_res, "claim", "amount"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "alice"));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            exec_msg,
+        )
+        .unwrap_err();
+
+        // The sender cannot take anything back before the deadline
+        let exec_msg = ExecuteMsg::Reclaim { transfer_id: 1 };
+        let exec_res =
+            execute(deps.as_mut(), env, mock_info("some_user", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::ClaimNotExpired {}, exec_res);
+    }
+
+    #[test]
+    fn test_reclaim_after_deadline() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let mut env = mock_env();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: Some(Expiration::AtHeight(env.block.height + 10)),
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+
+        // Past the deadline neither recipient can claim
+        env.block.height += 10;
+        let exec_msg = ExecuteMsg::Claim { transfer_id: 1 };
+        let exec_res =
+            execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::ClaimExpired {}, exec_res);
+
+        let query_msg = QueryMsg::ExpiredClaims {
+            sender: "some_user".into(),
+            start_after: None,
+            limit: None,
+        };
+        let resp: ClaimsResp =
+            from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(2, resp.claims.len());
+
+        // Only the sender can reclaim, and gets both shares back
+        let exec_msg = ExecuteMsg::Reclaim { transfer_id: 1 };
+        let exec_res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("bob", &[]),
+            exec_msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, exec_res);
+        let exec_res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("some_user", &[]),
+            exec_msg.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(98, "sei"),
+            }
+            .into()
+        );
+        let exec_res =
+            execute(deps.as_mut(), env, mock_info("some_user", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::NothingToReclaim {}, exec_res);
+    }
+
+    #[test]
+    fn test_accept_and_reject_transfers() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // Both recipients accept transfers themselves
+        for recipient in ["alice", "bob"] {
+            let exec_msg = ExecuteMsg::SetAcceptance {
+                window: Some(Duration::Height(10)),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(recipient, &[]), exec_msg).unwrap();
+        }
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+
+        let query_msg = QueryMsg::PendingAcceptances {
+            recipient: "bob".into(),
+            start_after: None,
+            limit: None,
+        };
+        let resp: ClaimsResp = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(49), resp.claims[0].amount);
+
+        // alice accepts, bob rejects back into the sender's balance
+        let exec_msg = ExecuteMsg::Accept { transfer_id: 1 };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        assert_eq!("49", event_attr(&exec_res, "accept", "amount"));
+        let exec_msg = ExecuteMsg::Reject { transfer_id: 1 };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap();
+        assert_eq!("some_user", event_attr(&exec_res, "reject", "sender"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "some_user"));
+    }
+
+    #[test]
+    fn test_unaccepted_transfer_times_out() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let mut env = mock_env();
+        let exec_msg = ExecuteMsg::SetAcceptance {
+            window: Some(Duration::Height(10)),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "bob"));
+
+        // Once the window passes alice can no longer accept, and the sender
+        // gets her share back as withdrawable balance
+        env.block.height += 10;
+        let exec_msg = ExecuteMsg::Accept { transfer_id: 1 };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::AcceptanceExpired {}, exec_res);
+        let exec_msg = ExecuteMsg::Reclaim { transfer_id: 1 };
+        let exec_res = execute(deps.as_mut(), env, mock_info("some_user", &[]), exec_msg).unwrap();
+        assert!(exec_res.messages.is_empty());
+        assert_eq!("49", event_attr(&exec_res, "reclaim", "credited"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "some_user"));
+    }
+
+    #[test]
+    fn test_cancel_partly_withdrawn_transfer() {
+        // Instantiate the contract, refunding fees on cancel
+        let mut instantiate_msg = instantiate_msg(2);
+        instantiate_msg.cancel_fee_mode = Some(CancelFeeMode::Refund);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: Some(Duration::Height(10)),
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+
+        // alice has already taken out 40 of her 49
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(40),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CancelTransfer { transfer_id: 1 };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, exec_res);
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("some_user", &[]), exec_msg.clone()).unwrap();
+        assert_eq!("49", event_attr(&exec_res, "cancel_transfer", "recipient_1_credited"));
+        assert_eq!("9", event_attr(&exec_res, "cancel_transfer", "recipient_1_reversed"));
+        assert_eq!("49", event_attr(&exec_res, "cancel_transfer", "recipient_2_reversed"));
+        assert_eq!("2", event_attr(&exec_res, "cancel_transfer", "fee_refunded"));
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(60, "sei"),
+            }
+            .into()
+        );
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "owner"));
+
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("some_user", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::AlreadyCancelled {}, exec_res);
+    }
+
+    #[test]
+    fn test_cancel_leaves_unrelated_credits() {
+        // Instantiate the contract, refunding fees on cancel
+        let mut instantiate_msg = instantiate_msg(2);
+        instantiate_msg.cancel_fee_mode = Some(CancelFeeMode::Refund);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: Some(Duration::Height(10)),
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("some_user", &coins(100, "sei")), exec_msg).unwrap();
+
+        // An unrelated transfer credits alice and the owner again
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "carol".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("dave", &coins(100, "sei")), exec_msg).unwrap();
+
+        // alice spends dave's 49 and 11 of the cancellable 49, the owner
+        // dave's fee and 1 of the cancellable fee
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(60),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(3),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+
+        // What is left of the cancellable credits comes back
+        let exec_msg = ExecuteMsg::CancelTransfer { transfer_id: 1 };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("some_user", &[]), exec_msg).unwrap();
+        assert_eq!("38", event_attr(&exec_res, "cancel_transfer", "recipient_1_reversed"));
+        assert_eq!("49", event_attr(&exec_res, "cancel_transfer", "recipient_2_reversed"));
+        assert_eq!("1", event_attr(&exec_res, "cancel_transfer", "fee_refunded"));
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(88, "sei"),
+            }
+            .into()
+        );
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "owner"));
+    }
+
+    #[test]
+    fn test_cancel_after_spending_other_balance() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // alice holds 499 from an ordinary transfer and 49 she may lose
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(1000),
+            recipient_1: "alice".into(),
+            recipient_2: "carol".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("dave", &coins(1000, "sei")), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: Some(Duration::Height(10)),
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("some_user", &coins(100, "sei")), exec_msg).unwrap();
+
+        // Withdrawing no more than the ordinary 499 leaves the credit alone
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(49),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CancelTransfer { transfer_id: 2 };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("some_user", &[]), exec_msg).unwrap();
+        assert_eq!("49", event_attr(&exec_res, "cancel_transfer", "recipient_1_reversed"));
+        assert_eq!(Uint128::new(450), query_balance_of(deps.as_ref(), "alice"));
+    }
+
+    #[test]
+    fn test_cancel_window_closed() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let mut env = mock_env();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: Some(Duration::Height(10)),
+            vesting: None,
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        execute(deps.as_mut(), env.clone(), info2.clone(), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CancelTransfer { transfer_id: 2 };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("some_user", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::NotCancellable {}, exec_res);
+
+        env.block.height += 10;
+        let exec_msg = ExecuteMsg::CancelTransfer { transfer_id: 1 };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("some_user", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::CancelWindowClosed {}, exec_res);
+
+        // Once the window has closed the credit is no longer tracked
+        let bob = Addr::unchecked("bob");
+        assert!(CANCELLABLE_CREDITS.has(&deps.storage, (&bob, 1)));
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(1),
+        };
+        execute(deps.as_mut(), env, mock_info("bob", &[]), exec_msg).unwrap();
+        assert!(!CANCELLABLE_CREDITS.has(&deps.storage, (&bob, 1)));
+    }
+
+    #[test]
+    fn test_vesting_transfer() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // Each share of 50 vests over 100 seconds
+        let mut env = mock_env();
+        let start = env.block.time;
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(102),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: Some(Vesting {
+                start,
+                end: start.plus_seconds(100),
+                cliff: None,
+            }),
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(102, "sei"));
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+
+        // A fifth of the way in only 10 can be withdrawn
+        env.block.time = start.plus_seconds(20);
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(11),
+        };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::NotEnoughBalance {}, exec_res);
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(10),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        // The balance counts what has vested since, and it can be spent
+        // without withdrawing first
+        env.block.time = start.plus_seconds(60);
+        let query_msg = QueryMsg::Balance {
+            address: "alice".into(),
+            height: None,
+        };
+        let resp: BalanceResp = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(20), resp.balance);
+        let exec_msg = ExecuteMsg::InternalTransfer {
+            to: "carol".into(),
+            amount: Uint128::new(20),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        let query_msg = QueryMsg::Vesting {
+            recipient: "alice".into(),
+        };
+        let resp: VestingResp = from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert_eq!(
+            VestingResp {
+                total: Uint128::new(50),
+                vested: Uint128::new(30),
+                withdrawn: Uint128::new(30),
+                locked: Uint128::new(20),
+            },
+            resp
+        );
+    }
+
+    #[test]
+    fn test_vesting_cliff() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let mut env = mock_env();
+        let start = env.block.time;
+        let vesting = Vesting {
+            start,
+            end: start.plus_seconds(100),
+            cliff: Some(start.plus_seconds(50)),
+        };
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(102),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: Some(vesting),
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(102, "sei"));
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+
+        // Nothing before the cliff, then everything vested since the start
+        env.block.time = start.plus_seconds(49);
+        let query_msg = QueryMsg::Vesting {
+            recipient: "bob".into(),
+        };
+        let resp: VestingResp = from_json(query(deps.as_ref(), env.clone(), query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(Uint128::new(50), resp.locked);
+        env.block.time = start.plus_seconds(50);
+        let resp: VestingResp = from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(25), resp.vested);
+
+        // A cliff after the end is rejected
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(102),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: Some(Vesting {
+                cliff: Some(start.plus_seconds(101)),
+                ..vesting
+            }),
+            msg: None,
+        };
+        let info2 = mock_info("some_user", &coins(102, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
+        assert_eq!(ContractError::InvalidVesting {}, exec_res);
+    }
+
+    #[test]
+    fn test_vesting_grants_bounded() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let mut env = mock_env();
+        let start = env.block.time;
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(4),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: Some(Vesting {
+                start,
+                end: start.plus_seconds(100),
+                cliff: None,
+            }),
+            msg: None,
+        };
+        let info2 = mock_info("mallory", &coins(4, "sei"));
+        for _ in 0..MAX_VESTING_GRANTS {
+            execute(deps.as_mut(), env.clone(), info2.clone(), exec_msg.clone()).unwrap();
+        }
+        let exec_res = execute(deps.as_mut(), env.clone(), info2.clone(), exec_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::TooManyGrants {}, exec_res);
+
+        // Grants are dropped once fully released, making room for more, even
+        // if that happens only when the next grant comes in
+        env.block.time = start.plus_seconds(100);
+        let withdraw = ExecuteMsg::Withdraw {
+            amount: Uint128::new(1),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), withdraw).unwrap();
+        let alice = Addr::unchecked("alice");
+        assert_eq!(0, VESTING.prefix(&alice).range(&deps.storage, None, None, Order::Ascending).count());
+        let bob = Addr::unchecked("bob");
+        assert_eq!(
+            MAX_VESTING_GRANTS,
+            VESTING.prefix(&bob).range(&deps.storage, None, None, Order::Ascending).count()
+        );
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(4),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: Some(Vesting {
+                start: env.block.time,
+                end: env.block.time.plus_seconds(100),
+                cliff: None,
+            }),
+            msg: None,
+        };
+        execute(deps.as_mut(), env, info2, exec_msg).unwrap();
+        assert_eq!(1, VESTING.prefix(&bob).range(&deps.storage, None, None, Order::Ascending).count());
+    }
+
+    #[test]
+    fn test_vesting_to_managed_recipients() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // alice accepts her transfers, carol has hers forwarded
+        let exec_msg = ExecuteMsg::SetAcceptance {
+            window: Some(Duration::Height(10)),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::SetForwarding {
+            preference: Some(ForwardPreference::Withdraw {}),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), exec_msg).unwrap();
+
+        // Neither can be sent a vesting share, which would bypass both
+        let start = mock_env().block.time;
+        for recipient in ["alice", "carol"] {
+            let exec_msg = ExecuteMsg::Transfer {
+                transfer_amount: Uint128::new(102),
+                recipient_1: recipient.into(),
+                recipient_2: "bob".into(),
+                claim_deadline: None,
+                cancel_window: None,
+                vesting: Some(Vesting {
+                    start,
+                    end: start.plus_seconds(100),
+                    cliff: None,
+                }),
+                msg: None,
+            };
+            let info2 = mock_info("some_user", &coins(102, "sei"));
+            let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
+            assert_eq!(ContractError::CannotVest {}, exec_res);
+        }
+        let resp = query_vesting(deps.as_ref(), mock_env(), "bob".into()).unwrap();
+        assert!(resp.total.is_zero());
+    }
+
+    #[test]
+    fn test_stream_accrues_and_cancels() {
         // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // 3 a second to each of two recipients for 100 seconds, plus the fee
+        let mut env = mock_env();
+        let start = env.block.time;
+        let exec_msg = ExecuteMsg::CreateStream {
+            recipients: vec!["alice".into(), "bob".into()],
+            rate_per_second: Uint128::new(3),
+            start,
+            end: start.plus_seconds(100),
+        };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("some_user", &coins(601, "sei")), exec_msg.clone()).unwrap_err();
+        assert_eq!(
+            ContractError::IncorrectFunds {
+                expected: Uint128::new(602),
+                received: Uint128::new(601),
+            },
+            exec_res
+        );
+        execute(deps.as_mut(), env.clone(), mock_info("some_user", &coins(602, "sei")), exec_msg).unwrap();
+
+        // Balances grow with block time and can be withdrawn straight away
+        env.block.time = start.plus_seconds(10);
+        let query_msg = QueryMsg::Balance {
+            address: "alice".into(),
+            height: None,
         };
+        let resp: BalanceResp = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(30), resp.balance);
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(30),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap();
+
+        // Cancelling refunds the 80 seconds left to both recipients
+        env.block.time = start.plus_seconds(20);
+        let exec_msg = ExecuteMsg::CancelStream { stream_id: 1 };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("some_user", &[]), exec_msg).unwrap();
+        assert_eq!("480", event_attr(&exec_res, "cancel_stream", "refund"));
+
+        env.block.time = start.plus_seconds(50);
+        let query_msg = QueryMsg::Stream { stream_id: 1 };
+        let resp: StreamResp = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(120), resp.streamed);
+        assert_eq!(Uint128::zero(), resp.remaining);
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(31),
+        };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::NotEnoughBalance {}, exec_res);
+
+        // Once everything from the ended stream is released it is forgotten
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(30),
+        };
+        execute(deps.as_mut(), env, mock_info("alice", &[]), exec_msg).unwrap();
+        assert!(!STREAMED.has(&deps.storage, (&Addr::unchecked("alice"), 1)));
+    }
+
+    #[test]
+    fn test_streams_bounded() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(0);
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
 
-        // query owner address
-        let query_msg = QueryMsg::Fee {};
-        let fee_resp: FeeResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
-        assert_eq!("1", fee_resp.fee);
+        let mut env = mock_env();
+        let start = env.block.time;
+        let exec_msg = ExecuteMsg::CreateStream {
+            recipients: vec!["alice".into()],
+            rate_per_second: Uint128::new(1),
+            start,
+            end: start.plus_seconds(10),
+        };
+        let info2 = mock_info("mallory", &coins(10, "sei"));
+        for _ in 0..MAX_STREAMS {
+            execute(deps.as_mut(), env.clone(), info2.clone(), exec_msg.clone()).unwrap();
+        }
+        let exec_res = execute(deps.as_mut(), env.clone(), info2.clone(), exec_msg).unwrap_err();
+        assert_eq!(ContractError::TooManyStreams {}, exec_res);
+
+        // Ended streams are released and dropped to make room for a new one
+        env.block.time = start.plus_seconds(10);
+        let exec_msg = ExecuteMsg::CreateStream {
+            recipients: vec!["alice".into()],
+            rate_per_second: Uint128::new(1),
+            start: env.block.time,
+            end: env.block.time.plus_seconds(10),
+        };
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+        let alice = Addr::unchecked("alice");
+        assert_eq!(1, STREAMED.prefix(&alice).range(&deps.storage, None, None, Order::Ascending).count());
+        assert_eq!(Uint128::new(300), query_balance_of(deps.as_ref(), "alice"));
+    }
+
+    #[test]
+    fn test_stream_top_up() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(0);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let env = mock_env();
+        let start = env.block.time;
+        let exec_msg = ExecuteMsg::CreateStream {
+            recipients: vec!["alice".into()],
+            rate_per_second: Uint128::new(4),
+            start,
+            end: start.plus_seconds(10),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("some_user", &coins(40, "sei")), exec_msg).unwrap();
+
+        // Only the stream's sender can extend it
+        let exec_msg = ExecuteMsg::TopUpStream { stream_id: 1 };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("mallory", &coins(4, "sei")), exec_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, exec_res);
+
+        // 22 pays for five more seconds, with 2 sent back
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("some_user", &coins(22, "sei")), exec_msg).unwrap();
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(2, "sei"),
+            }
+            .into()
+        );
+        let query_msg = QueryMsg::Stream { stream_id: 1 };
+        let resp: StreamResp = from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert_eq!(start.plus_seconds(15), resp.stream.end);
+        assert_eq!(Uint128::new(60), resp.remaining);
+    }
+
+    #[test]
+    fn test_scheduled_transfers_by_keeper() {
+        // Instantiate the contract with a keeper bounty of 1
+        let mut instantiate_msg = instantiate_msg(2);
+        instantiate_msg.keeper_bounty = Some(Uint128::new(1));
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        // Two monthly-style instalments of 100, each with a bounty
+        let mut env = mock_env();
+        let exec_msg = ExecuteMsg::CreateSchedule {
+            amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            interval: Duration::Time(30),
+            count: 2,
+        };
+        let info2 = mock_info("some_user", &coins(202, "sei"));
+        let exec_res = execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+        assert_eq!("1", event_attr(&exec_res, "schedule_created", "schedule_id"));
+        assert_eq!("2", event_attr(&exec_res, "schedule_created", "count"));
+        assert_eq!("1", event_attr(&exec_res, "schedule_created", "bounty"));
+
+        // The first instalment is due straight away
+        let exec_msg = ExecuteMsg::ExecuteDue { schedule_id: 1 };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), exec_msg.clone()).unwrap();
+        assert_eq!("1", event_attr(&exec_res, "scheduled_transfer", "transfer_id"));
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "keeper".into(),
+                amount: coins(1, "sei"),
+            }
+            .into()
+        );
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "alice"));
+
+        // The next is not due until a full interval later
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), exec_msg.clone()).unwrap_err();
+        assert_eq!(
+            ContractError::NotDue {
+                next_due: Expiration::AtTime(env.block.time.plus_seconds(30)),
+            },
+            exec_res
+        );
+        env.block.time = env.block.time.plus_seconds(30);
+        execute(deps.as_mut(), env.clone(), mock_info("keeper", &[]), exec_msg.clone()).unwrap();
+        assert_eq!(Uint128::new(98), query_balance_of(deps.as_ref(), "bob"));
+
+        env.block.time = env.block.time.plus_seconds(30);
+        let exec_res = execute(deps.as_mut(), env, mock_info("keeper", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::ScheduleFinished {}, exec_res);
+    }
+
+    #[test]
+    fn test_cancel_schedule() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CreateSchedule {
+            amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            interval: Duration::Height(100),
+            count: 3,
+        };
+        let info2 = mock_info("some_user", &coins(300, "sei"));
+        execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::ExecuteDue { schedule_id: 1 };
+        execute(deps.as_mut(), mock_env(), mock_info("keeper", &[]), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CancelSchedule { schedule_id: 1 };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("some_user", &[]), exec_msg).unwrap();
+        assert_eq!("200", event_attr(&exec_res, "cancel_schedule", "refund"));
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(200, "sei"),
+            }
+            .into()
+        );
+        let query_msg = QueryMsg::Schedule { schedule_id: 1 };
+        let schedule: Schedule = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(0, schedule.remaining);
+    }
+
+    #[test]
+    fn test_hash_locked_transfer_reveal() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let preimage = hex::encode(b"swap secret");
+        let hash = hex::encode(Sha256::digest(b"swap secret"));
+        let env = mock_env();
+        let exec_msg = ExecuteMsg::HashLockedTransfer {
+            transfer_amount: Uint128::new(100),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            hash,
+            expires: Expiration::AtHeight(env.block.height + 10),
+        };
+        let info2 = mock_info("some_user", &coins(100, "sei"));
+        execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "owner"));
+
+        let exec_msg = ExecuteMsg::RevealPreimage {
+            lock_id: 1,
+            preimage: hex::encode(b"wrong guess"),
+        };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::InvalidPreimage {}, exec_res);
+
+        let exec_msg = ExecuteMsg::RevealPreimage {
+            lock_id: 1,
+            preimage: preimage.clone(),
+        };
+        let exec_res = execute(deps.as_mut(), env, mock_info("anyone", &[]), exec_msg).unwrap();
+        assert_eq!(preimage, event_attr(&exec_res, "hash_lock_released", "preimage"));
+        assert_eq!("some_user", event_attr(&exec_res, "split_transfer", "sender"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(2), query_balance_of(deps.as_ref(), "owner"));
+    }
+
+    #[test]
+    fn test_hash_locked_transfer_refund() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let mut env = mock_env();
+        let exec_msg = ExecuteMsg::HashLockedTransfer {
+            transfer_amount: Uint128::new(101),
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            hash: hex::encode(Sha256::digest(b"swap secret")),
+            expires: Expiration::AtHeight(env.block.height + 10),
+        };
+        let info2 = mock_info("some_user", &coins(101, "sei"));
+        let exec_res = execute(deps.as_mut(), env.clone(), info2, exec_msg).unwrap();
+        assert_eq!("1", event_attr(&exec_res, "hash_lock_created", "lock_id"));
+        assert_eq!("49", event_attr(&exec_res, "hash_lock_created", "recipient_amount"));
+
+        let exec_msg = ExecuteMsg::RefundHashLock { lock_id: 1 };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), exec_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::HashLockNotExpired {}, exec_res);
+
+        // After expiry the preimage is no good and the sender gets it all back
+        env.block.height += 10;
+        let reveal_msg = ExecuteMsg::RevealPreimage {
+            lock_id: 1,
+            preimage: hex::encode(b"swap secret"),
+        };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), reveal_msg).unwrap_err();
+        assert_eq!(ContractError::HashLockExpired {}, exec_res);
+        let exec_res = execute(deps.as_mut(), env, mock_info("anyone", &[]), exec_msg).unwrap();
+        assert_eq!("100", event_attr(&exec_res, "refund_hash_lock", "amount"));
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(100, "sei"),
+            }
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_escrow_partial_release_and_refund() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CreateEscrow {
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            arbiter: "arbiter".into(),
+            joint_approval: false,
+        };
+        let info2 = mock_info("some_user", &coins(102, "sei"));
+        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+        assert_eq!("100", event_attr(&exec_res, "escrow_created", "amount"));
+        assert_eq!("arbiter", event_attr(&exec_res, "escrow_created", "arbiter"));
+        assert_eq!(Uint128::new(2), query_balance_of(deps.as_ref(), "owner"));
+
+        // The arbiter releases the first milestone
+        let exec_msg = ExecuteMsg::ReleaseEscrow {
+            escrow_id: 1,
+            amount: Some(Uint128::new(40)),
+        };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, exec_res);
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), exec_msg).unwrap();
+        assert_eq!("60", event_attr(&exec_res, "escrow_release", "remaining"));
+        assert_eq!(Uint128::new(20), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(20), query_balance_of(deps.as_ref(), "bob"));
+
+        // A dispute returns the rest to the sender
+        let exec_msg = ExecuteMsg::RefundEscrow { escrow_id: 1 };
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), exec_msg).unwrap();
+        assert_eq!("60", event_attr(&exec_res, "refund_escrow", "refund"));
+        assert_eq!(
+            exec_res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(60, "sei"),
+            }
+            .into()
+        );
+        let query_msg = QueryMsg::Escrow { escrow_id: 1 };
+        let escrow: Escrow = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(40), escrow.released);
+        assert_eq!(Uint128::new(60), escrow.refunded);
+        assert_eq!(Uint128::zero(), escrow.remaining);
+    }
+
+    #[test]
+    fn test_escrow_joint_approval() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::CreateEscrow {
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            arbiter: "arbiter".into(),
+            joint_approval: true,
+        };
+        let info2 = mock_info("some_user", &coins(102, "sei"));
+        execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
+
+        // The arbiter's approval alone releases nothing, even if repeated
+        let exec_msg = ExecuteMsg::ReleaseEscrow {
+            escrow_id: 1,
+            amount: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), exec_msg.clone()).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), exec_msg.clone()).unwrap();
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "alice"));
+
+        let exec_res = execute(deps.as_mut(), mock_env(), mock_info("some_user", &[]), exec_msg).unwrap();
+        assert_eq!("50", event_attr(&exec_res, "escrow_release", "recipient_amount"));
+        assert_eq!(Uint128::new(50), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(50), query_balance_of(deps.as_ref(), "bob"));
+    }
+
+    #[test]
+    fn test_pay_invoice() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let env = mock_env();
+        let exec_msg = ExecuteMsg::CreateInvoice {
+            amount: Uint128::new(100),
+            denom: "sei".into(),
+            co_recipient: Some("bob".into()),
+            expires: Expiration::AtHeight(env.block.height + 10),
+        };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap();
+        assert_eq!("bob", event_attr(&exec_res, "invoice_created", "recipient_2"));
+        let invoice_id: u64 = from_json(exec_res.data.unwrap()).unwrap();
+        assert_eq!(1, invoice_id);
+
+        // Anyone can pay, but only the exact amount
+        let exec_msg = ExecuteMsg::PayInvoice { invoice_id };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("payer", &coins(101, "sei")), exec_msg.clone()).unwrap_err();
+        assert_eq!(
+            ContractError::IncorrectFunds {
+                expected: Uint128::new(100),
+                received: Uint128::new(101),
+            },
+            exec_res
+        );
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("payer", &coins(100, "sei")), exec_msg.clone()).unwrap();
+        assert_eq!("payer", event_attr(&exec_res, "split_transfer", "sender"));
+        assert_eq!("1", event_attr(&exec_res, "invoice_paid", "transfer_id"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "alice"));
+        assert_eq!(Uint128::new(49), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(2), query_balance_of(deps.as_ref(), "owner"));
+
+        let query_msg = QueryMsg::Invoice { invoice_id };
+        let invoice: Invoice = from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(Some(Addr::unchecked("payer")), invoice.paid_by);
+        assert_eq!(Some(1), invoice.transfer_id);
+
+        let exec_res = execute(deps.as_mut(), env, mock_info("payer", &coins(100, "sei")), exec_msg).unwrap_err();
+        assert_eq!(ContractError::InvoicePaid {}, exec_res);
+    }
+
+    #[test]
+    fn test_invoice_expired_or_wrong_denom() {
+        // Instantiate the contract
+        let instantiate_msg = instantiate_msg(2);
+        let mut deps = mock_dependencies();
+        let info = mock_info(&String::from("some_user"), &[]);
+        instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
+
+        let mut env = mock_env();
+        let exec_msg = ExecuteMsg::CreateInvoice {
+            amount: Uint128::new(100),
+            denom: "uatom".into(),
+            co_recipient: None,
+            expires: Expiration::AtHeight(env.block.height + 10),
+        };
+        let exec_res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::UnsupportedDenom { denom: "sei".into() }, exec_res);
+
+        let exec_msg = ExecuteMsg::CreateInvoice {
+            amount: Uint128::new(100),
+            denom: "sei".into(),
+            co_recipient: None,
+            expires: Expiration::AtHeight(env.block.height + 10),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), exec_msg).unwrap();
+        env.block.height += 10;
+        let exec_msg = ExecuteMsg::PayInvoice { invoice_id: 1 };
+        let exec_res = execute(deps.as_mut(), env, mock_info("payer", &coins(100, "sei")), exec_msg).unwrap_err();
+        assert_eq!(ContractError::InvoiceExpired {}, exec_res);
+    }
+
+    /// Delegations move coins into a pool account. Unbonding is left to the
+    /// test, which completes it by sending coins back from the pool.
+    struct TestStaking;
+
+    const STAKING_POOL: &str = "staking_pool";
+
+    impl Module for TestStaking {
+        type ExecT = StakingMsg;
+        type QueryT = StakingQuery;
+        type SudoT = StakingSudo;
+
+        fn execute<ExecC, QueryC>(
+            &self,
+            api: &dyn Api,
+            storage: &mut dyn Storage,
+            router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+            block: &BlockInfo,
+            sender: Addr,
+            msg: StakingMsg,
+        ) -> anyhow::Result<AppResponse>
+        where
+            ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+            QueryC: CustomQuery + DeserializeOwned + 'static,
+        {
+            match msg {
+                StakingMsg::Delegate { amount, .. } => {
+                    let send = BankMsg::Send {
+                        to_address: STAKING_POOL.to_owned(),
+                        amount: vec![amount],
+                    };
+                    router.execute(api, storage, block, sender, send.into())
+                }
+                StakingMsg::Undelegate { .. } | StakingMsg::Redelegate { .. } => {
+                    Ok(AppResponse::default())
+                }
+                _ => anyhow::bail!("unsupported staking message"),
+            }
+        }
+
+        fn sudo<ExecC, QueryC>(
+            &self,
+            _api: &dyn Api,
+            _storage: &mut dyn Storage,
+            _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+            _block: &BlockInfo,
+            _msg: StakingSudo,
+        ) -> anyhow::Result<AppResponse>
+        where
+            ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+            QueryC: CustomQuery + DeserializeOwned + 'static,
+        {
+            anyhow::bail!("unsupported staking sudo")
+        }
+
+        fn query(
+            &self,
+            _api: &dyn Api,
+            _storage: &dyn Storage,
+            _querier: &dyn Querier,
+            _block: &BlockInfo,
+            request: StakingQuery,
+        ) -> anyhow::Result<Binary> {
+            match request {
+                // Rewards are not simulated, so there are never any to book
+                StakingQuery::Delegation { .. } => {
+                    Ok(to_json_binary(&DelegationResponse { delegation: None })?)
+                }
+                _ => anyhow::bail!("unsupported staking query"),
+            }
+        }
+    }
+
+    impl Staking for TestStaking {}
+
+    #[test]
+    fn test_staking_queues_withdrawals_beyond_buffer() {
+        let alice = Addr::unchecked("alice");
+        let bob = Addr::unchecked("bob");
+        let owner = Addr::unchecked("owner");
+        let mut app = AppBuilder::new()
+            .with_staking(TestStaking)
+            .build(|router, _, storage| {
+                router
+                    .bank
+                    .init_balance(storage, &alice, coins(1000, "sei"))
+                    .unwrap();
+            });
+        let code_id = app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+        let contract = app
+            .instantiate_contract(code_id, owner.clone(), &instantiate_msg(0), &[], "split", None)
+            .unwrap();
+
+        let transfer = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(1000),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        app.execute_contract(alice, contract.clone(), &transfer, &coins(1000, "sei"))
+            .unwrap();
+
+        let config = Some(StakingConfig {
+            validator: "validator".to_owned(),
+            buffer: Uint128::new(100),
+        });
+        app.execute_contract(owner, contract.clone(), &ExecuteMsg::SetStaking { config }, &[])
+            .unwrap();
+        app.execute_contract(bob.clone(), contract.clone(), &ExecuteMsg::Rebalance {}, &[])
+            .unwrap();
+        assert_eq!(
+            Uint128::new(900),
+            app.wrap().query_balance(STAKING_POOL, "sei").unwrap().amount
+        );
+
+        // Within the buffer the withdrawal is paid straight away
+        let withdraw = ExecuteMsg::Withdraw {
+            amount: Uint128::new(50),
+        };
+        app.execute_contract(bob.clone(), contract.clone(), &withdraw, &[])
+            .unwrap();
+        assert_eq!(Uint128::new(50), app.wrap().query_balance(&bob, "sei").unwrap().amount);
+
+        // Beyond it the withdrawal is queued and the shortfall unbonded
+        let withdraw = ExecuteMsg::Withdraw {
+            amount: Uint128::new(450),
+        };
+        let res = app
+            .execute_contract(bob.clone(), contract.clone(), &withdraw, &[])
+            .unwrap();
+        assert!(res.has_event(&Event::new("wasm").add_attribute("queued", "true")));
+        assert_eq!(Uint128::new(50), app.wrap().query_balance(&bob, "sei").unwrap().amount);
+        let staking: StakingResp = app
+            .wrap()
+            .query_wasm_smart(&contract, &QueryMsg::Staking {})
+            .unwrap();
+        assert_eq!(Uint128::new(400), staking.delegated);
+        assert_eq!(Uint128::new(450), staking.queued);
+
+        let err = app
+            .execute_contract(bob.clone(), contract.clone(), &ExecuteMsg::ClaimWithdrawal {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::StillUnbonding {}, err.downcast().unwrap());
+
+        // Unbonding completes once the unbonding period has passed
+        app.send_tokens(Addr::unchecked(STAKING_POOL), contract.clone(), &coins(500, "sei"))
+            .unwrap();
+        let err = app
+            .execute_contract(bob.clone(), contract.clone(), &ExecuteMsg::ClaimWithdrawal {}, &[])
+            .unwrap_err();
+        assert_eq!(ContractError::StillUnbonding {}, err.downcast().unwrap());
+        app.update_block(|block| block.time = block.time.plus_seconds(21 * 24 * 60 * 60));
+        app.execute_contract(bob.clone(), contract.clone(), &ExecuteMsg::ClaimWithdrawal {}, &[])
+            .unwrap();
+        assert_eq!(Uint128::new(500), app.wrap().query_balance(&bob, "sei").unwrap().amount);
+        assert_eq!(
+            Uint128::new(100),
+            app.wrap().query_balance(&contract, "sei").unwrap().amount
+        );
+        let queued: QueuedWithdrawalResp = app
+            .wrap()
+            .query_wasm_smart(&contract, &QueryMsg::QueuedWithdrawal { address: "bob".to_owned() })
+            .unwrap();
+        assert_eq!(Uint128::zero(), queued.amount);
+    }
+
+    #[test]
+    fn test_rebalance_leaves_reserved_funds() {
+        let mut deps = mock_dependencies_with_balance(&coins(500, "sei"));
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        let config = Some(StakingConfig {
+            validator: "validator".to_owned(),
+            buffer: Uint128::new(100),
+        });
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), ExecuteMsg::SetStaking { config })
+            .unwrap();
+
+        // Escrowed funds are never delegated
+        let exec_msg = ExecuteMsg::CreateEscrow {
+            recipient_1: "alice".into(),
+            recipient_2: "bob".into(),
+            arbiter: "arbiter".into(),
+            joint_approval: false,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("some_user", &coins(500, "sei")), exec_msg).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::Rebalance {})
+            .unwrap();
+        assert_eq!("0", event_attr(&res, RebalanceEvent::TYPE, "delegated"));
+        assert!(res.messages.is_empty());
+
+        // Only balances above the buffer are
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(300),
+            recipient_1: "carol".into(),
+            recipient_2: "dave".into(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("some_user", &coins(300, "sei")), exec_msg).unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(800, "sei"));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::Rebalance {})
+            .unwrap();
+        assert_eq!("200", event_attr(&res, RebalanceEvent::TYPE, "delegated"));
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(600, "sei"));
+
+        // So a refund is paid straight away from what was kept back
+        let exec_msg = ExecuteMsg::RefundEscrow { escrow_id: 1 };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("arbiter", &[]), exec_msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "some_user".into(),
+                amount: coins(500, "sei"),
+            }
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_set_staking_moves_delegations() {
+        let mut deps = mock_dependencies_with_balance(&coins(300, "sei"));
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::Rebalance {})
+            .unwrap_err();
+        assert_eq!(ContractError::StakingDisabled {}, err);
+
+        let config = |validator: &str| {
+            Some(StakingConfig {
+                validator: validator.to_owned(),
+                buffer: Uint128::new(100),
+            })
+        };
+        let exec_msg = ExecuteMsg::SetStaking { config: config("validator") };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), exec_msg.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::Rebalance {})
+            .unwrap();
+        assert_eq!(
+            CosmosMsg::Staking(StakingMsg::Delegate {
+                validator: "validator".to_owned(),
+                amount: coin(200, "sei"),
+            }),
+            res.messages[0].msg
+        );
+
+        let exec_msg = ExecuteMsg::SetStaking { config: config("other") };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator: "validator".to_owned(),
+                dst_validator: "other".to_owned(),
+                amount: coin(200, "sei"),
+            }),
+            res.messages[0].msg
+        );
+
+        let exec_msg = ExecuteMsg::SetStaking { config: None };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Staking(StakingMsg::Undelegate {
+                validator: "other".to_owned(),
+                amount: coin(200, "sei"),
+            }),
+            res.messages[0].msg
+        );
+        let staking: StakingResp =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::Staking {}).unwrap()).unwrap();
+        assert_eq!(None, staking.config);
+        assert_eq!(Uint128::zero(), staking.delegated);
+    }
+
+    #[test]
+    fn test_withdraw_after_disabling_staking() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        let config = Some(StakingConfig {
+            validator: "validator".to_owned(),
+            buffer: Uint128::new(100),
+        });
+        let exec_msg = ExecuteMsg::SetStaking { config };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+        fund_bob(deps.as_mut());
+        rebalance(&mut deps, 400);
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(100, "sei"));
+
+        // Only 100 is held while the 300 undelegated comes back
+        let exec_msg = ExecuteMsg::SetStaking { config: None };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+        let withdraw = |amount| ExecuteMsg::Withdraw {
+            amount: Uint128::new(amount),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), withdraw(200)).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!("true", attr(&res.attributes, "queued"));
+
+        // Once unbonded, bob's withdrawal is paid and payouts go straight out
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(21 * 24 * 60 * 60);
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(400, "sei"));
+        let res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), ExecuteMsg::ClaimWithdrawal {})
+            .unwrap();
+        assert_eq!("200", event_attr(&res, ClaimWithdrawalEvent::TYPE, "amount"));
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(200, "sei"));
+        let res = execute(deps.as_mut(), env, mock_info("carol", &[]), withdraw(200)).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "carol".to_owned(),
+                amount: coins(200, "sei"),
+            }),
+            res.messages[0].msg
+        );
+    }
+
+    #[test]
+    fn test_delegate_balance_and_claim_after_unbonding() {
+        let alice = Addr::unchecked("alice");
+        let bob = Addr::unchecked("bob");
+        let mut app = AppBuilder::new()
+            .with_staking(TestStaking)
+            .build(|router, _, storage| {
+                router
+                    .bank
+                    .init_balance(storage, &alice, coins(1000, "sei"))
+                    .unwrap();
+            });
+        let code_id = app.store_code(Box::new(ContractWrapper::new(execute, instantiate, query)));
+        let contract = app
+            .instantiate_contract(code_id, alice.clone(), &instantiate_msg(0), &[], "split", None)
+            .unwrap();
+        let transfer = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(1000),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        app.execute_contract(alice, contract.clone(), &transfer, &coins(1000, "sei"))
+            .unwrap();
+
+        let exec_msg = ExecuteMsg::DelegateBalance {
+            validator: "validator".to_owned(),
+            amount: Uint128::new(300),
+        };
+        app.execute_contract(bob.clone(), contract.clone(), &exec_msg, &[])
+            .unwrap();
+        assert_eq!(
+            Uint128::new(300),
+            app.wrap().query_balance(STAKING_POOL, "sei").unwrap().amount
+        );
+        let balance: BalanceResp = app
+            .wrap()
+            .query_wasm_smart(&contract, &QueryMsg::Balance { address: "bob".to_owned(), height: None })
+            .unwrap();
+        assert_eq!(Uint128::new(200), balance.balance);
+
+        let exec_msg = ExecuteMsg::UndelegateBalance {
+            validator: "validator".to_owned(),
+            amount: Uint128::new(100),
+        };
+        app.execute_contract(bob.clone(), contract.clone(), &exec_msg, &[])
+            .unwrap();
+        let delegations: DelegationsResp = app
+            .wrap()
+            .query_wasm_smart(&contract, &QueryMsg::Delegations { address: "bob".to_owned() })
+            .unwrap();
+        assert_eq!(
+            vec![UserDelegation {
+                validator: "validator".to_owned(),
+                amount: Uint128::new(200),
+            }],
+            delegations.delegations
+        );
+
+        // Unbonding completes and the coins can be claimed
+        app.send_tokens(Addr::unchecked(STAKING_POOL), contract.clone(), &coins(100, "sei"))
+            .unwrap();
+        app.update_block(|block| block.time = block.time.plus_seconds(21 * 24 * 60 * 60));
+        app.execute_contract(bob.clone(), contract, &ExecuteMsg::ClaimWithdrawal {}, &[])
+            .unwrap();
+        assert_eq!(Uint128::new(100), app.wrap().query_balance(&bob, "sei").unwrap().amount);
+    }
+
+    #[test]
+    fn test_redelegate_balance_moves_tracked_delegation() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(400),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(400, "sei")), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::DelegateBalance {
+            validator: "validator".to_owned(),
+            amount: Uint128::zero(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::ZeroDelegation {}, err);
+
+        // Only coins the contract actually holds free can be bonded
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(100, "sei"));
+        let exec_msg = ExecuteMsg::DelegateBalance {
+            validator: "validator".to_owned(),
+            amount: Uint128::new(150),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg.clone()).unwrap_err();
+        assert_eq!(
+            ContractError::NotEnoughLiquid {
+                available: Uint128::new(100)
+            },
+            err
+        );
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(400, "sei"));
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::RedelegateBalance {
+            src_validator: "validator".to_owned(),
+            dst_validator: "other".to_owned(),
+            amount: Uint128::new(200),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap_err();
+        assert_eq!(
+            ContractError::NotEnoughDelegated {
+                delegated: Uint128::new(150)
+            },
+            err
+        );
+        let exec_msg = ExecuteMsg::RedelegateBalance {
+            src_validator: "validator".to_owned(),
+            dst_validator: "other".to_owned(),
+            amount: Uint128::new(50),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator: "validator".to_owned(),
+                dst_validator: "other".to_owned(),
+                amount: coin(50, "sei"),
+            }),
+            res.messages[0].msg
+        );
+
+        let query_msg = QueryMsg::Delegations {
+            address: "bob".to_owned(),
+        };
+        let resp: DelegationsResp = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                UserDelegation {
+                    validator: "other".to_owned(),
+                    amount: Uint128::new(50),
+                },
+                UserDelegation {
+                    validator: "validator".to_owned(),
+                    amount: Uint128::new(100),
+                },
+            ],
+            resp.delegations
+        );
+    }
+
+    /// Sends bob's `amount` over IBC.
+    /// Delivers `packet` on channel-1 and runs the `ReceiveSplit` it
+    /// dispatches, reverting its writes on failure as the chain would.
+    /// Returns the packet's acknowledgement.
+    fn receive_packet(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, packet: &SplitPacket) -> IbcAck {
+        let recv = mock_ibc_packet_recv("channel-1", packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), recv).unwrap();
+        let exec_msg = match &res.messages[..] {
+            [SubMsg {
+                id: RECEIVE_SPLIT_REPLY_ID,
+                msg: CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }),
+                ..
+            }] => from_json(msg).unwrap(),
+            _ => return from_json(&res.acknowledgement).unwrap(),
+        };
+
+        let snapshot: Vec<_> = deps.storage.range(None, None, Order::Ascending).collect();
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let result = match execute(deps.as_mut(), mock_env(), info, exec_msg) {
+            Ok(res) => {
+                let mut data = vec![];
+                encode_bytes(&mut data, 1, &res.data.unwrap_or_default());
+                SubMsgResult::Ok(SubMsgResponse {
+                    events: res.events,
+                    data: Some(data.into()),
+                })
+            }
+            Err(err) => {
+                let written: Vec<_> = deps.storage.range(None, None, Order::Ascending).collect();
+                for (key, _) in written {
+                    deps.storage.remove(&key);
+                }
+                for (key, value) in snapshot {
+                    deps.storage.set(&key, &value);
+                }
+                SubMsgResult::Err(err.to_string())
+            }
+        };
+        let reply_msg = Reply {
+            id: RECEIVE_SPLIT_REPLY_ID,
+            result,
+        };
+        let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+        from_json(res.data.unwrap()).unwrap()
+    }
+
+    fn withdraw_ibc(deps: DepsMut, amount: u128) {
+        let exec_msg = ExecuteMsg::WithdrawIbc {
+            channel_id: "channel-0".to_owned(),
+            remote_address: "cosmos1remote".to_owned(),
+            amount: Uint128::new(amount),
+            timeout: mock_env().block.time.plus_seconds(600),
+        };
+        let res = execute(deps, mock_env(), mock_info("bob", &[]), exec_msg).unwrap();
+        let value = encode_msg_transfer(
+            "channel-0",
+            &coin(amount, "sei"),
+            MOCK_CONTRACT_ADDR,
+            "cosmos1remote",
+            mock_env().block.time.plus_seconds(600),
+            &format!(r#"{{"ibc_callback":"{}"}}"#, MOCK_CONTRACT_ADDR),
+        );
+        assert_eq!(
+            SubMsg::reply_on_success(
+                CosmosMsg::Stargate {
+                    type_url: "/ibc.applications.transfer.v1.MsgTransfer".to_owned(),
+                    value,
+                },
+                IBC_TRANSFER_REPLY_ID,
+            ),
+            res.messages[0]
+        );
+    }
+
+    fn reply_sequence(deps: DepsMut, sequence: u8) {
+        let reply_msg = Reply {
+            id: IBC_TRANSFER_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(Binary::from(vec![0x08, sequence])),
+            }),
+        };
+        reply(deps, mock_env(), reply_msg).unwrap();
+    }
+
+    fn fund_bob(deps: DepsMut) {
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(400),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
+        };
+        execute(deps, mock_env(), mock_info("alice", &coins(400, "sei")), exec_msg).unwrap();
+    }
+
+    #[test]
+    fn test_delegation_rewards_credited_to_delegators() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        enable_staking(deps.as_mut());
+        fund_bob(deps.as_mut());
+        rebalance(&mut deps, 1200);
+        for delegator in ["bob", "carol"] {
+            let exec_msg = ExecuteMsg::DelegateBalance {
+                validator: "validator".to_owned(),
+                amount: Uint128::new(100),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(delegator, &[]), exec_msg).unwrap();
+        }
+
+        // Half the stake is pooled and half is delegated by bob and carol
+        accrue_rewards(&mut deps.querier, &[("validator", 80)]);
+        let exec_msg = ExecuteMsg::UndelegateBalance {
+            validator: "validator".to_owned(),
+            amount: Uint128::new(50),
+        };
+        let env = next_block(deps.as_ref(), "validator");
+        execute(deps.as_mut(), env, mock_info("carol", &[]), exec_msg).unwrap();
+        assert_eq!(Uint128::new(40), query_pending_rewards_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(40), query_pending_rewards_of(deps.as_ref(), "carol"));
+
+        // Claiming withdraws what bob's delegation has earned since
+        accrue_rewards(&mut deps.querier, &[("validator", 70)]);
+        let env = next_block(deps.as_ref(), "validator");
+        let res = execute(deps.as_mut(), env, mock_info("bob", &[]), ExecuteMsg::ClaimRewards {})
+            .unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+                    validator: "validator".to_owned(),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "bob".to_owned(),
+                    amount: coins(80, "sei"),
+                }),
+            ],
+            res.messages.into_iter().map(|sub| sub.msg).collect::<Vec<_>>()
+        );
+        assert_eq!(Uint128::new(70), query_pending_rewards_of(deps.as_ref(), "carol"));
+    }
+
+    #[test]
+    fn test_claim_withdrawal_in_queue_order() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        let mut env = mock_env();
+        queue_withdrawal(deps.as_mut().storage, &env, &Addr::unchecked("bob"), Uint128::new(100))
+            .unwrap();
+        queue_withdrawal(deps.as_mut().storage, &env, &Addr::unchecked("carol"), Uint128::new(100))
+            .unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(150, "sei"));
+
+        // Nothing can be claimed before the unbonding period has passed
+        let claim = ExecuteMsg::ClaimWithdrawal {};
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), claim.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::StillUnbonding {}, err);
+
+        // Carol's entry is behind bob's, so the funds go to bob first
+        env.block.time = env.block.time.plus_seconds(21 * 24 * 60 * 60);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), claim.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::StillUnbonding {}, err);
+        let res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), claim.clone()).unwrap();
+        assert_eq!("100", event_attr(&res, ClaimWithdrawalEvent::TYPE, "amount"));
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(50, "sei"));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), claim.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::NothingQueued {}, err);
+
+        // Reserved funds are not used for the queue
+        RESERVED.save(deps.as_mut().storage, &Uint128::new(50)).unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(120, "sei"));
+        let err = execute(deps.as_mut(), env.clone(), mock_info("carol", &[]), claim.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::StillUnbonding {}, err);
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(150, "sei"));
+        execute(deps.as_mut(), env, mock_info("carol", &[]), claim).unwrap();
+    }
+
+    #[test]
+    fn test_withdraw_ibc_restored_on_timeout() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        fund_bob(deps.as_mut());
+
+        let exec_msg = ExecuteMsg::WithdrawIbc {
+            channel_id: "channel-0".to_owned(),
+            remote_address: "cosmos1remote".to_owned(),
+            amount: Uint128::new(150),
+            timeout: mock_env().block.time,
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::InvalidExpiration {}, err);
+
+        // Nothing is sent unless the contract holds it free right now
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(100, "sei"));
+        let exec_msg = ExecuteMsg::WithdrawIbc {
+            channel_id: "channel-0".to_owned(),
+            remote_address: "cosmos1remote".to_owned(),
+            amount: Uint128::new(150),
+            timeout: mock_env().block.time.plus_seconds(600),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap_err();
+        assert_eq!(
+            ContractError::NotEnoughLiquid {
+                available: Uint128::new(100)
+            },
+            err
+        );
+
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(400, "sei"));
+        withdraw_ibc(deps.as_mut(), 150);
+        reply_sequence(deps.as_mut(), 29);
+        assert_eq!(Uint128::new(50), query_balance_of(deps.as_ref(), "bob"));
+
+        let timeout: SudoMsg = from_json(
+            br#"{"ibc_lifecycle_complete":{"ibc_timeout":{"channel":"channel-0","sequence":29}}}"#,
+        )
+        .unwrap();
+        let res = sudo(deps.as_mut(), mock_env(), timeout.clone()).unwrap();
+        assert_eq!("timeout", attr(&res.events[0].attributes, "reason"));
+        assert_eq!(Uint128::new(200), query_balance_of(deps.as_ref(), "bob"));
+
+        // A repeated callback finds nothing left to refund
+        sudo(deps.as_mut(), mock_env(), timeout).unwrap();
+        assert_eq!(Uint128::new(200), query_balance_of(deps.as_ref(), "bob"));
+    }
+
+    #[test]
+    fn test_encode_msg_transfer() {
+        let value = encode_msg_transfer(
+            "channel-0",
+            &coin(5, "sei"),
+            "c",
+            "r",
+            Timestamp::from_nanos(300),
+            "{}",
+        );
+        let mut expected = b"\x0a\x08transfer\x12\x09channel-0".to_vec();
+        expected.extend_from_slice(b"\x1a\x08\x0a\x03sei\x12\x015");
+        expected.extend_from_slice(b"\x22\x01c\x2a\x01r\x38\xac\x02\x42\x02{}");
+        assert_eq!(Binary::from(expected), value);
+    }
+
+    #[test]
+    fn test_withdraw_ibc_settled_by_ack() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        fund_bob(deps.as_mut());
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(400, "sei"));
+        withdraw_ibc(deps.as_mut(), 50);
+        reply_sequence(deps.as_mut(), 29);
+        withdraw_ibc(deps.as_mut(), 100);
+        reply_sequence(deps.as_mut(), 30);
+        assert_eq!(Uint128::new(50), query_balance_of(deps.as_ref(), "bob"));
+
+        let ack = |sequence: u64, ack: &str, success: bool| {
+            SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcAck {
+                channel: "channel-0".to_owned(),
+                sequence,
+                ack: ack.to_owned(),
+                success,
+            })
+        };
+        sudo(deps.as_mut(), mock_env(), ack(29, r#"{"result":"AQ=="}"#, true)).unwrap();
+        assert_eq!(Uint128::new(50), query_balance_of(deps.as_ref(), "bob"));
+
+        let error = r#"{"error":"invalid receiver"}"#;
+        let res = sudo(deps.as_mut(), mock_env(), ack(30, error, false)).unwrap();
+        assert_eq!(error, attr(&res.events[0].attributes, "reason"));
+        assert_eq!(Uint128::new(150), query_balance_of(deps.as_ref(), "bob"));
+
+        // The settled withdrawal is not refunded by a late failure
+        sudo(deps.as_mut(), mock_env(), ack(29, error, false)).unwrap();
+        assert_eq!(Uint128::new(150), query_balance_of(deps.as_ref(), "bob"));
     }
 
     #[test]
-    fn test_query_owner_balance() {
-        // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
+    fn test_ibc_channel_handshake() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        let mut msg = instantiate_msg(0);
+        msg.ibc_counterparty_port = None;
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
 
-        // The owner should have 0sei
-        let owner = "owner".into();
-        let query_msg = QueryMsg::Balance { address: owner };
-        let balance_resp: BalanceResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
-        assert_eq!(Uint128::new(0), balance_resp.balance);
+        let open = mock_ibc_channel_open_init("channel-1", IbcOrder::Ordered, IBC_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(ContractError::InvalidIbcOrder {}, err);
+        let open = mock_ibc_channel_open_try("channel-1", IbcOrder::Unordered, "ics20-1");
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(
+            ContractError::InvalidIbcVersion {
+                version: IBC_VERSION.to_owned()
+            },
+            err
+        );
+        let open = mock_ibc_channel_open_try("channel-1", IbcOrder::Unordered, IBC_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open.clone()).unwrap_err();
+        assert_eq!(ContractError::InvalidCounterparty {}, err);
+
+        // Only the configured counterpart contract's port may open a channel
+        let exec_msg = ExecuteMsg::SetIbcCounterparty {
+            port_id: "their_port".to_owned(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), exec_msg.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+        ibc_channel_open(deps.as_mut(), mock_env(), open.clone()).unwrap();
+        let exec_msg = ExecuteMsg::SetIbcCounterparty {
+            port_id: "other_port".to_owned(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), exec_msg).unwrap();
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(ContractError::InvalidCounterparty {}, err);
+
+        let exec_msg = ExecuteMsg::FundRemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        let info = mock_info("alice", &coins(100, "sei"));
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), exec_msg.clone()).unwrap_err();
+        assert_eq!(ContractError::UnknownChannel {}, err);
+
+        let connect = mock_ibc_channel_connect_confirm("channel-1", IbcOrder::Unordered, IBC_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        execute(deps.as_mut(), mock_env(), info.clone(), exec_msg.clone()).unwrap();
+
+        let close = mock_ibc_channel_close_init("channel-1", IbcOrder::Unordered, IBC_VERSION);
+        ibc_channel_close(deps.as_mut(), mock_env(), close).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap_err();
+        assert_eq!(ContractError::UnknownChannel {}, err);
     }
+
     #[test]
-    fn test_transfer_even_amount() {
-        // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(2),
-        };
+    fn test_ibc_split_packet_spends_escrow() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(2)).unwrap();
+        let connect = mock_ibc_channel_connect_ack("channel-1", IbcOrder::Unordered, IBC_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let exec_msg = ExecuteMsg::FundRemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(300, "sei")), exec_msg).unwrap();
 
-        // send 100sei, owner gets 2, recipients get 49sei. None left for the sender
-        let info2 = mock_info(&String::from("some_user"), &balance);
-        let recipient_1 = "recipient_1".into();
-        let recipient_2 = "recipient_2".into();
-        let exec_msg = ExecuteMsg::Transfer {
-            transfer_amount: Uint128::new(100),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+        let packet = SplitPacket {
+            sender: "remote".to_owned(),
+            amount: Uint128::new(200),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
         };
-        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
-        assert_eq!(("action", "transfer"), exec_res.attributes[0]);
-        assert_eq!(("recipient_1", "recipient_1"), exec_res.attributes[1]);
-        assert_eq!(("recipient_1_recieved", "49"), exec_res.attributes[4]);
-        assert_eq!(("owner_recieved", "2"), exec_res.attributes[6]);
-        assert_eq!(("sender_charged", "100"), exec_res.attributes[7]);
-    }
+        let ack = receive_packet(&mut deps, &packet);
+        assert_eq!(IbcAck::Result(to_json_binary(&1u64).unwrap()), ack);
+        assert_eq!(Uint128::new(99), query_balance_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(2), query_balance_of(deps.as_ref(), "owner"));
 
+        // The rest of the escrow cannot cover a second split
+        let ack = receive_packet(&mut deps, &packet);
+        assert_eq!(IbcAck::Error("Only 100 is escrowed for this account".to_owned()), ack);
+        assert_eq!(Uint128::new(99), query_balance_of(deps.as_ref(), "bob"));
+        let query_msg = QueryMsg::RemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        let resp: RemoteEscrowResp = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(100), resp.amount);
+    }
 
     #[test]
-    fn test_transfer_odd_amount() {
-        // Instantiate the contract
-        let fee = Uint128::new(2);
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: fee,
-        };
+    fn test_ibc_split_packet_reverted_when_forward_fails() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(2)).unwrap();
+        let connect = mock_ibc_channel_connect_ack("channel-1", IbcOrder::Unordered, IBC_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let exec_msg = ExecuteMsg::FundRemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(300, "sei")), exec_msg).unwrap();
 
-        // send 99sei, owner gets 2, recipients get 48sei. 1sei left for the sender
-        let info2 = mock_info(&String::from("some_user"), &balance);
-        let recipient_1 = "recipient_1".into();
-        let recipient_2 = "recipient_2".into();
-        let exec_msg = ExecuteMsg::Transfer {
-            transfer_amount: Uint128::new(99),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+        // Only the contract itself may run a split
+        let packet = SplitPacket {
+            sender: "remote".to_owned(),
+            amount: Uint128::new(200),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
         };
-        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
-        assert_eq!(("action", "transfer"), exec_res.attributes[0]);
-        assert_eq!(("recipient_1", "recipient_1"), exec_res.attributes[1]);
-        assert_eq!(("recipient_1_recieved", "48"), exec_res.attributes[4]);
-        assert_eq!(("owner_recieved", fee.to_string()), exec_res.attributes[6]);
-        assert_eq!(("sender_charged", "98"), exec_res.attributes[7]);
+        let exec_msg = ExecuteMsg::ReceiveSplit {
+            channel_id: "channel-1".to_owned(),
+            packet: packet.clone(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("remote", &[]), exec_msg).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+
+        // bob forwards his credits, but a full queue makes the payout fail
+        enable_staking(deps.as_mut());
+        let exec_msg = ExecuteMsg::SetForwarding {
+            preference: Some(ForwardPreference::Withdraw {}),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap();
+        QUEUED_TOTAL.save(deps.as_mut().storage, &Uint128::MAX).unwrap();
+
+        let ack = receive_packet(&mut deps, &packet);
+        let overflow = OverflowError::new(OverflowOperation::Add, Uint128::MAX, 99u128);
+        assert_eq!(IbcAck::Error(StdError::from(overflow).to_string()), ack);
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "carol"));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "owner"));
+        let query_msg = QueryMsg::RemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        let resp: RemoteEscrowResp = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(Uint128::new(300), resp.amount);
     }
 
     #[test]
-    fn test_transfer_fee_plus_1_error() {
-        // Instantiate the contract
-        let fee = Uint128::new(2);
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: fee,
-        };
+    fn test_refund_remote_escrow_after_channel_closes() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(2)).unwrap();
+        let connect = mock_ibc_channel_connect_ack("channel-1", IbcOrder::Unordered, IBC_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let exec_msg = ExecuteMsg::FundRemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(300, "sei")), exec_msg.clone())
+            .unwrap();
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &coins(50, "sei")), exec_msg)
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
 
-        // send 3sei, owner gets 2, recipients get 1sei and 0sei?. Should throw RecipientPaidZeroOrOneCoin error
-        let info2 = mock_info(&String::from("some_user"), &balance);
-        let recipient_1 = "recipient_1".into();
-        let recipient_2 = "recipient_2".into();
+        let packet = SplitPacket {
+            sender: "remote".to_owned(),
+            amount: Uint128::new(200),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
+        };
+        receive_packet(&mut deps, &packet);
+
+        // The remote sender may still spend the escrow while the channel is open
+        let refund = ExecuteMsg::RefundRemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), refund.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::ChannelOpen {}, err);
+        let close = mock_ibc_channel_close_init("channel-1", IbcOrder::Unordered, IBC_VERSION);
+        ibc_channel_close(deps.as_mut(), mock_env(), close).unwrap();
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), refund.clone())
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), refund.clone())
+            .unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_owned(),
+                amount: coins(100, "sei"),
+            }),
+            res.messages[0].msg
+        );
+        assert_eq!(Uint128::zero(), RESERVED.load(deps.as_ref().storage).unwrap());
+        let query_msg = QueryMsg::RemoteEscrow {
+            channel_id: "channel-1".to_owned(),
+            remote_sender: "remote".to_owned(),
+        };
+        let resp: RemoteEscrowResp = from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(
+            RemoteEscrowResp {
+                amount: Uint128::zero(),
+                funder: None,
+            },
+            resp
+        );
+        let err = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), refund).unwrap_err();
+        assert_eq!(ContractError::NothingEscrowed {}, err);
+    }
+
+    /// Transfers 400 to a "dao" contract and bob, with `msg` for the hook.
+    fn transfer_to_dao(deps: DepsMut) -> Response {
         let exec_msg = ExecuteMsg::Transfer {
-            transfer_amount: Uint128::new(3),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+            transfer_amount: Uint128::new(400),
+            recipient_1: "dao".to_owned(),
+            recipient_2: "bob".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: Some(Binary::from(b"proposal-7".to_vec())),
         };
-        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
-        assert_eq!(ContractError::RecipientPaidZeroOrOneCoin {}, exec_res);
+        execute(deps, mock_env(), mock_info("alice", &coins(400, "sei")), exec_msg).unwrap()
+    }
 
+    fn mock_dao_contract(querier: &mut MockQuerier) {
+        querier.update_wasm(|query| match query {
+            WasmQuery::ContractInfo { contract_addr } if contract_addr == "dao" => {
+                let info = to_json_binary(&ContractInfoResponse::default()).unwrap();
+                SystemResult::Ok(ContractResult::Ok(info))
+            }
+            _ => SystemResult::Err(SystemError::NoSuchContract {
+                addr: "other".to_owned(),
+            }),
+        });
     }
 
     #[test]
-    fn test_transfer_fee_error() {
-        // Instantiate the contract
-        let fee = Uint128::new(2);
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: fee,
-        };
+    fn test_split_received_hook_failure_is_ignored() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        mock_dao_contract(&mut deps.querier);
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
 
-        // send 2sei, owner gets 2, recipients get 0sei and 0sei?. Should throw RecipientPaidZeroOrOneCoin error
-        let info2 = mock_info(&String::from("some_user"), &balance);
-        let recipient_1 = "recipient_1".into();
-        let recipient_2 = "recipient_2".into();
-        let exec_msg = ExecuteMsg::Transfer {
-            transfer_amount: Uint128::new(3),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+        let res = transfer_to_dao(deps.as_mut());
+        let hook = ReceiverMsg::SplitReceived {
+            sender: "alice".to_owned(),
+            amount: Uint128::new(200),
+            msg: Binary::from(b"proposal-7".to_vec()),
         };
-        let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
-        assert_eq!(ContractError::RecipientPaidZeroOrOneCoin {}, exec_res);
+        let hooks: Vec<_> = res.messages.iter().filter(|sub| sub.id == HOOK_REPLY_ID).collect();
+        assert_eq!(1, hooks.len());
+        assert_eq!(ReplyOn::Error, hooks[0].reply_on);
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "dao".to_owned(),
+                msg: to_json_binary(&hook).unwrap(),
+                funds: vec![],
+            }),
+            hooks[0].msg
+        );
 
+        let reply_msg = Reply {
+            id: HOOK_REPLY_ID,
+            result: SubMsgResult::Err("dao is paused".to_owned()),
+        };
+        let res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+        assert_eq!("dao is paused", attr(&res.attributes, "hook_error"));
+        assert_eq!(Uint128::new(200), query_balance_of(deps.as_ref(), "dao"));
     }
 
     #[test]
-    fn test_query_zero_balance() {
-        // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
+    fn test_split_received_hook_skips_pending_shares() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        mock_dao_contract(&mut deps.querier);
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        let transfer = |claim_deadline| ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(400),
+            recipient_1: "dao".to_owned(),
+            recipient_2: "bob".to_owned(),
+            claim_deadline,
+            cancel_window: None,
+            vesting: None,
+            msg: Some(Binary::from(b"proposal-7".to_vec())),
+        };
+        let info = mock_info("alice", &coins(400, "sei"));
 
-        // Users with no balance should be given a zero balance.
-        let recipient_1 = "no_bal_user".into();
-        let query_msg = QueryMsg::Balance {
-            address: recipient_1,
+        // The dao's share waits for it to claim, so nothing is announced yet
+        let deadline = Some(Expiration::AtHeight(mock_env().block.height + 100));
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), transfer(deadline)).unwrap();
+        assert!(!res.messages.iter().any(|sub| matches!(sub.msg, CosmosMsg::Wasm(_))));
+        assert_eq!(Uint128::zero(), query_balance_of(deps.as_ref(), "dao"));
+
+        // Nor while the dao has yet to accept a transfer
+        let exec_msg = ExecuteMsg::SetAcceptance {
+            window: Some(Duration::Height(100)),
         };
-        let balance_resp: BalanceResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
-        assert_eq!(Uint128::new(0), balance_resp.balance);
+        execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), exec_msg).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), transfer(None)).unwrap();
+        assert!(!res.messages.iter().any(|sub| matches!(sub.msg, CosmosMsg::Wasm(_))));
+
+        // Nor when the dao's share is forwarded straight on
+        let exec_msg = ExecuteMsg::SetAcceptance { window: None };
+        execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::SetForwarding {
+            preference: Some(ForwardPreference::ForwardTo {
+                address: "treasury".to_owned(),
+            }),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("dao", &[]), exec_msg).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), info, transfer(None)).unwrap();
+        assert!(!res.messages.iter().any(|sub| matches!(sub.msg, CosmosMsg::Wasm(_))));
+        assert!(res.messages.iter().any(|sub| sub.msg
+            == CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".to_owned(),
+                amount: coins(200, "sei"),
+            })));
     }
 
     #[test]
-    fn test_query_nonzero_balance() {
-        // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
+    fn test_split_received_hook_can_revert_transfer() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        mock_dao_contract(&mut deps.querier);
+        let mut msg = instantiate_msg(0);
+        msg.hook_failure_mode = Some(HookFailureMode::Revert);
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
 
-        // transfer 100sei with a 1sei fee.  Recipients should get 49sei, and owner should get 1. Overall, the sender is deducted 99sei.
-        let info2 = mock_info(&String::from("some_user"), &balance);
-        let recipient_1 = "recipient_1".into();
-        let recipient_2 = "recipient_2".into();
+        let res = transfer_to_dao(deps.as_mut());
+        let hooks: Vec<_> = res
+            .messages
+            .iter()
+            .filter(|sub| matches!(sub.msg, CosmosMsg::Wasm(_)))
+            .collect();
+        assert_eq!(1, hooks.len());
+        assert_eq!(ReplyOn::Never, hooks[0].reply_on);
+
+        // Without a msg no hook is sent
         let exec_msg = ExecuteMsg::Transfer {
-            transfer_amount: Uint128::new(100),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+            transfer_amount: Uint128::new(400),
+            recipient_1: "dao".to_owned(),
+            recipient_2: "bob".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
         };
-        let exec_res: Response = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
-        assert_eq!(("action", "transfer"), exec_res.attributes[0]);
-        assert_eq!(("recipient_1", "recipient_1"), exec_res.attributes[1]);
-        assert_eq!(("recipient_1_recieved", "49"), exec_res.attributes[4]);
-        assert_eq!(("owner_recieved", "1"), exec_res.attributes[6]);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(400, "sei")), exec_msg)
+            .unwrap();
+        assert!(!res.messages.iter().any(|sub| matches!(sub.msg, CosmosMsg::Wasm(_))));
+    }
 
-        // Each recipient should now have 49sei
-        let recipient_1 = "recipient_1".into();
-        let query_msg = QueryMsg::Balance {
-            address: recipient_1,
+    /// Makes the contract's delegations show the given rewards accrued, by
+    /// validator.
+    fn accrue_rewards(querier: &mut MockQuerier, rewards: &[(&str, u128)]) {
+        let delegations: Vec<_> = rewards
+            .iter()
+            .map(|(validator, rewards)| FullDelegation {
+                delegator: Addr::unchecked(MOCK_CONTRACT_ADDR),
+                validator: validator.to_string(),
+                amount: coin(1000, "sei"),
+                can_redelegate: coin(1000, "sei"),
+                accumulated_rewards: coins(*rewards, "sei"),
+            })
+            .collect();
+        querier.update_staking("sei", &[], &delegations);
+    }
+
+    /// An env for the block after the validator's rewards were last booked.
+    fn next_block(deps: Deps, validator: &str) -> Env {
+        let mut env = mock_env();
+        let booked = REWARDS_BOOKED.may_load(deps.storage, validator).unwrap();
+        env.block.height = booked.unwrap_or(env.block.height) + 1;
+        env
+    }
+
+    /// Runs `HarvestRewards` with the validator paying out `rewards`.
+    fn harvest(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, rewards: u128) {
+        accrue_rewards(&mut deps.querier, &[("validator", rewards)]);
+        let env = next_block(deps.as_ref(), "validator");
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::HarvestRewards {})
+            .unwrap();
+        assert_eq!(rewards.to_string(), event_attr(&res, HarvestRewardsEvent::TYPE, "rewards"));
+        assert_eq!(
+            CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+                validator: "validator".to_owned(),
+            }),
+            res.messages[0].msg
+        );
+        accrue_rewards(&mut deps.querier, &[("validator", 0)]);
+    }
+
+    /// Delegates whatever the contract holds above the 1000 buffer.
+    fn rebalance(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, held: u128) {
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(held, "sei"));
+        execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), ExecuteMsg::Rebalance {})
+            .unwrap();
+    }
+
+    fn query_pending_rewards_of(deps: Deps, address: &str) -> Uint128 {
+        let query_msg = QueryMsg::PendingRewards {
+            address: address.into(),
         };
-        let balance_resp: BalanceResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
-        assert_eq!(Uint128::new(49), balance_resp.balance);
+        let resp: PendingRewardsResp = from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+        resp.amount
+    }
+
+    fn enable_staking(deps: DepsMut) {
+        let config = Some(StakingConfig {
+            validator: "validator".to_owned(),
+            buffer: Uint128::new(1000),
+        });
+        execute(deps, mock_env(), mock_info("owner", &[]), ExecuteMsg::SetStaking { config })
+            .unwrap();
     }
 
     #[test]
-    fn test_withdraw_nonzero_amount() {
-        // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
+    fn test_claim_rewards_pays_pro_rata_share() {
         let mut deps = mock_dependencies();
-        let balance = coins(100, "sei");
-        let info = mock_info(&String::from("some_user"), &balance);
-        let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        enable_staking(deps.as_mut());
 
-        // send 100sei, owner gets 1, recipients get 49sei. 1sei left for the sender
-        let info2 = mock_info(&String::from("some_user"), &balance);
-        let recipient_1 = "recipient_1".into();
-        let recipient_2 = "recipient_2".into();
         let exec_msg = ExecuteMsg::Transfer {
-            transfer_amount: Uint128::new(100),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+            transfer_amount: Uint128::new(400),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
         };
-        let exec_res: Response = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap();
-        assert_eq!(("action", "transfer"), exec_res.attributes[0]);
-        assert_eq!(("recipient_1", "recipient_1"), exec_res.attributes[1]);
-        assert_eq!(("recipient_1_recieved", "49"), exec_res.attributes[4]);
-        assert_eq!(("owner_recieved", "1"), exec_res.attributes[6]);
-
-        // Each recipient should now have 49sei
-        let recipient_1 = "recipient_1".into();
-        let query_msg = QueryMsg::Balance {
-            address: recipient_1,
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(400, "sei")), exec_msg).unwrap();
+        let exec_msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(100),
         };
-        let balance_resp: BalanceResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
-        assert_eq!(Uint128::new(49), balance_resp.balance);
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(1000, "sei"));
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), exec_msg).unwrap();
 
-        // The recpient should be able to withdraw the 49sei
-        let info_recip = mock_info(&String::from("recipient_1"), &balance);
-        let exec_msg = ExecuteMsg::Withdraw {
-            amount: Uint128::new(49),
+        rebalance(&mut deps, 1300);
+        harvest(&mut deps, 90);
+        assert_eq!(Uint128::new(30), query_pending_rewards_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(60), query_pending_rewards_of(deps.as_ref(), "carol"));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), ExecuteMsg::ClaimRewards {})
+            .unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "bob".to_owned(),
+                amount: coins(30, "sei"),
+            }),
+            res.messages[0].msg
+        );
+        assert_eq!(Uint128::zero(), query_pending_rewards_of(deps.as_ref(), "bob"));
+        let err = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), ExecuteMsg::ClaimRewards {})
+            .unwrap_err();
+        assert_eq!(ContractError::NoRewards {}, err);
+    }
+
+    #[test]
+    fn test_rewards_accrue_only_while_balance_held() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        enable_staking(deps.as_mut());
+
+        let transfer = |recipient_1: &str, recipient_2: &str| ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(200),
+            recipient_1: recipient_1.to_owned(),
+            recipient_2: recipient_2.to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
         };
-        let exec_res: Response = execute(deps.as_mut(), mock_env(), info_recip, exec_msg).unwrap();
-        assert_eq!(("action", "withdraw"), exec_res.attributes[0]);
-        assert_eq!(("sender", "recipient_1"), exec_res.attributes[1]);
-        assert_eq!(("withdraw_amount", "49"), exec_res.attributes[2]);
+        let info = mock_info("alice", &coins(200, "sei"));
+        execute(deps.as_mut(), mock_env(), info.clone(), transfer("bob", "carol")).unwrap();
+        rebalance(&mut deps, 1200);
+        harvest(&mut deps, 60);
 
-        // recipient_1 should now have 0sei
-        let recipient_1 = "recipient_1".into();
-        let query_msg = QueryMsg::Balance {
-            address: recipient_1,
+        // Dave joins after the first harvest and only shares in the second.
+        // A third of the stake is then not in any balance, so its share of
+        // the rewards stays with the contract.
+        execute(deps.as_mut(), mock_env(), info, transfer("bob", "dave")).unwrap();
+        rebalance(&mut deps, 1400);
+        harvest(&mut deps, 120);
+        assert_eq!(Uint128::new(70), query_pending_rewards_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(50), query_pending_rewards_of(deps.as_ref(), "carol"));
+        assert_eq!(Uint128::new(20), query_pending_rewards_of(deps.as_ref(), "dave"));
+
+        let reply_msg = Reply {
+            id: 99,
+            result: SubMsgResult::Err("failed".to_owned()),
         };
-        let balance_resp: BalanceResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
-        assert_eq!(Uint128::new(0), balance_resp.balance);
+        let err = reply(deps.as_mut(), mock_env(), reply_msg).unwrap_err();
+        assert_eq!(ContractError::UnknownReply { id: 99 }, err);
+    }
 
-        // recipient_2 should still have 49sei
-        let recipient_1 = "recipient_2".into();
-        let query_msg = QueryMsg::Balance {
-            address: recipient_1,
+    #[test]
+    fn test_rewards_booked_before_redelegation() {
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), instantiate_msg(0)).unwrap();
+        enable_staking(deps.as_mut());
+        let exec_msg = ExecuteMsg::Transfer {
+            transfer_amount: Uint128::new(400),
+            recipient_1: "bob".to_owned(),
+            recipient_2: "carol".to_owned(),
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
         };
-        let balance_resp: BalanceResp =
-            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
-        assert_eq!(Uint128::new(49), balance_resp.balance);
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(400, "sei")), exec_msg).unwrap();
+        rebalance(&mut deps, 1400);
+        harvest(&mut deps, 40);
+
+        // Moving the stake withdraws what it earned since the last harvest
+        accrue_rewards(&mut deps.querier, &[("validator", 100), ("validator2", 0)]);
+        let config = Some(StakingConfig {
+            validator: "validator2".to_owned(),
+            buffer: Uint128::new(1000),
+        });
+        let env = next_block(deps.as_ref(), "validator");
+        let res = execute(deps.as_mut(), env, mock_info("owner", &[]), ExecuteMsg::SetStaking { config })
+            .unwrap();
+        assert_eq!(
+            CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator: "validator".to_owned(),
+                dst_validator: "validator2".to_owned(),
+                amount: coin(400, "sei"),
+            }),
+            res.messages[0].msg
+        );
+        assert_eq!(Uint128::new(70), query_pending_rewards_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(70), query_pending_rewards_of(deps.as_ref(), "carol"));
+
+        // The next harvest is from the new validator
+        accrue_rewards(&mut deps.querier, &[("validator", 0), ("validator2", 60)]);
+        let env = next_block(deps.as_ref(), "validator2");
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::HarvestRewards {})
+            .unwrap();
+        assert_eq!(
+            CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+                validator: "validator2".to_owned(),
+            }),
+            res.messages[0].msg
+        );
+        assert_eq!(Uint128::new(100), query_pending_rewards_of(deps.as_ref(), "bob"));
+        assert_eq!(Uint128::new(100), query_pending_rewards_of(deps.as_ref(), "carol"));
     }
 
     #[test]
     fn test_withdraw_not_enough_balance_error() {
         // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
+        let instantiate_msg = instantiate_msg(1);
         let mut deps = mock_dependencies();
         let balance = coins(101, "sei");
         let info = mock_info(&String::from("some_user"), &balance);
@@ -557,11 +7554,7 @@ mod tests {
     #[test]
     fn test_transfer_less_than_fee_error() {
         // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(10000),
-        };
+        let instantiate_msg = instantiate_msg(10000);
         let mut deps = mock_dependencies();
         let balance = coins(10, "sei");
         let info = mock_info(&String::from("some_user"), &balance);
@@ -574,8 +7567,12 @@ mod tests {
         let recipient_2 = "recipient_2".into();
         let exec_msg = ExecuteMsg::Transfer {
             transfer_amount: Uint128::new(100),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
         };
         let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
         assert_eq!(ContractError::SentLessThanFee {  }, exec_res);
@@ -586,11 +7583,7 @@ mod tests {
     #[test]
     fn test_transfer_not_enough_coin_error() {
         // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
+        let instantiate_msg = instantiate_msg(1);
         let mut deps = mock_dependencies();
         let balance = coins(10, "sei");
         let info = mock_info(&String::from("some_user"), &balance);
@@ -603,8 +7596,12 @@ mod tests {
         let recipient_2 = "recipient_2".into();
         let exec_msg = ExecuteMsg::Transfer {
             transfer_amount: Uint128::new(100),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
         };
         let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
         assert_eq!(ContractError::NotEnoughCoin {}, exec_res);
@@ -614,11 +7611,7 @@ mod tests {
     #[test]
     fn test_transfer_wrong_coin_denom() {
         // Instantiate the contract
-        let instantiate_msg = InstantiateMsg {
-            coin_denom: "sei".to_owned(),
-            owner: "owner".to_owned(),
-            fee: Uint128::new(1),
-        };
+        let instantiate_msg = instantiate_msg(1);
         let mut deps = mock_dependencies();
         let balance = coins(0, "not_sei");
         let info = mock_info(&String::from("some_user"), &balance);
@@ -631,8 +7624,12 @@ mod tests {
         let recipient_2 = "recipient_2".into();
         let exec_msg = ExecuteMsg::Transfer {
             transfer_amount: Uint128::new(100),
-            recipient_1: recipient_1,
-            recipient_2: recipient_2,
+            recipient_1,
+            recipient_2,
+            claim_deadline: None,
+            cancel_window: None,
+            vesting: None,
+            msg: None,
         };
         let exec_res = execute(deps.as_mut(), mock_env(), info2, exec_msg).unwrap_err();
         assert_eq!(ContractError::SentIncorrectCoin {}, exec_res);
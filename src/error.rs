@@ -1,4 +1,5 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{OverflowError, StdError, Uint128};
+use cw_utils::Expiration;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -20,5 +21,173 @@ pub enum ContractError {
     
     #[error("Only enough coins to pay recipients either no coins, or an uneven amount of coins (ie transfer_amount = fee + 1")]
     RecipientPaidZeroOrOneCoin {},
-    
+
+    #[error("At least one recipient is required")]
+    NoRecipients {},
+
+    #[error("A transfer is split between exactly two recipients")]
+    InvalidRecipientCount {},
+
+    #[error("A batch must contain at least one transfer")]
+    EmptyBatch {},
+
+    #[error("Expected {expected} coins to be attached, received {received}")]
+    IncorrectFunds { expected: Uint128, received: Uint128 },
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Split recipients must each have a non-zero number of shares")]
+    ZeroShares {},
+
+    #[error("Split shares add up to more than a u64 can hold")]
+    SharesOverflow {},
+
+    #[error("Split is locked and can no longer be updated")]
+    SplitLocked {},
+
+    #[error("Split would pay into itself")]
+    SplitCycle {},
+
+    #[error("Splits can be nested at most {max} levels deep")]
+    SplitTooDeep { max: u32 },
+
+    #[error("Cannot set an allowance for your own account")]
+    CannotSetOwnAccount {},
+
+    #[error("Expiration is already in the past")]
+    InvalidExpiration {},
+
+    #[error("Allowance is expired")]
+    Expired {},
+
+    #[error("No allowance for this account, or not enough left")]
+    NoAllowance {},
+
+    #[error("Claim has expired")]
+    ClaimExpired {},
+
+    #[error("Claims on this transfer have not expired yet")]
+    ClaimNotExpired {},
+
+    #[error("Acceptance window has passed")]
+    AcceptanceExpired {},
+
+    #[error("Transfer was made without a cancel window")]
+    NotCancellable {},
+
+    #[error("Cancel window has passed")]
+    CancelWindowClosed {},
+
+    #[error("Transfer is already cancelled")]
+    AlreadyCancelled {},
+
+    #[error("Vesting must end after it starts, with any cliff in between")]
+    InvalidVesting {},
+
+    #[error("A transfer cannot have both a claim deadline and vesting")]
+    ConflictingTerms {},
+
+    #[error("Recipient accepts transfers or forwards its credits, so cannot be sent vesting ones")]
+    CannotVest {},
+
+    #[error("Recipient already has the most unfinished vesting grants allowed")]
+    TooManyGrants {},
+
+    #[error("Recipient is already receiving the most unfinished streams allowed")]
+    TooManyStreams {},
+
+    #[error("Stream must have a rate and end after it starts, no earlier than now")]
+    InvalidStream {},
+
+    #[error("Stream has already ended")]
+    StreamEnded {},
+
+    #[error("Schedule is not due until {next_due}")]
+    NotDue { next_due: Expiration },
+
+    #[error("Schedule needs at least one instalment")]
+    NoInstalments {},
+
+    #[error("Schedule has no instalments left")]
+    ScheduleFinished {},
+
+    #[error("Hash must be a hex encoded SHA-256 hash")]
+    InvalidHash {},
+
+    #[error("Preimage does not match the hash")]
+    InvalidPreimage {},
+
+    #[error("Hash lock has expired")]
+    HashLockExpired {},
+
+    #[error("Hash lock has not expired yet")]
+    HashLockNotExpired {},
+
+    #[error("Cannot release more than the {remaining} left in escrow")]
+    ExceedsEscrow { remaining: Uint128 },
+
+    #[error("Only {denom} is accepted")]
+    UnsupportedDenom { denom: String },
+
+    #[error("Invoice is already paid")]
+    InvoicePaid {},
+
+    #[error("Invoice has expired")]
+    InvoiceExpired {},
+
+    #[error("Staking is not configured")]
+    StakingDisabled {},
+
+    #[error("No withdrawal is queued for this address")]
+    NothingQueued {},
+
+    #[error("Queued withdrawal is still unbonding")]
+    StillUnbonding {},
+
+    #[error("Delegation amount must be non-zero")]
+    ZeroDelegation {},
+
+    #[error("Only {available} of the contract's funds is free right now")]
+    NotEnoughLiquid { available: Uint128 },
+
+    #[error("Only {delegated} is delegated to this validator")]
+    NotEnoughDelegated { delegated: Uint128 },
+
+    #[error("Only unordered channels are supported")]
+    InvalidIbcOrder {},
+
+    #[error("Channel version must be `{version}`")]
+    InvalidIbcVersion { version: String },
+
+    #[error("Channel is not open")]
+    UnknownChannel {},
+
+    #[error("Channel counterparty is not the counterpart contract")]
+    InvalidCounterparty {},
+
+    #[error("Channel is still open")]
+    ChannelOpen {},
+
+    #[error("Nothing is escrowed for this account")]
+    NothingEscrowed {},
+
+    #[error("Only {escrowed} is escrowed for this account")]
+    NotEnoughEscrow { escrowed: Uint128 },
+
+    #[error("IBC transfer response carried no packet sequence")]
+    InvalidTransferResponse {},
+
+    #[error("No rewards to claim")]
+    NoRewards {},
+
+    #[error("Unknown reply id {id}")]
+    UnknownReply { id: u64 },
+
+    #[error("Transfer has no unclaimed shares")]
+    NothingToReclaim {},
+
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
 }
@@ -0,0 +1,1358 @@
+use cosmwasm_std::{Addr, Attribute, Event, Timestamp, Uint128};
+use cw_utils::{Duration, Expiration};
+
+use crate::msg::Vesting;
+use crate::state::{Forwarding, SplitPayee, StakingConfig};
+
+/// Emitted for every successful `Transfer`. The chain prefixes custom event
+/// types with `wasm-`, so indexers see this as `wasm-split_transfer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitTransferEvent {
+    pub transfer_id: u64,
+    pub sender: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    pub owner: Addr,
+    pub recipient_amount: Uint128,
+    pub fee: Uint128,
+    pub sender_charged: Uint128,
+    pub refund: Uint128,
+    pub claim_deadline: Option<Expiration>,
+    pub vesting: Option<Vesting>,
+}
+
+impl SplitTransferEvent {
+    pub const TYPE: &'static str = "split_transfer";
+
+    /// The flat attributes emitted before typed events existed, keys and
+    /// order unchanged so existing indexers keep working.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "transfer"),
+            Attribute::new("recipient_1", self.recipient_1.as_str()),
+            Attribute::new("recipient_2", self.recipient_2.as_str()),
+            Attribute::new("owner", self.owner.as_str()),
+            Attribute::new("recipient_1_recieved", self.recipient_amount.to_string()),
+            Attribute::new("recipient_2_recieved", self.recipient_amount.to_string()),
+            Attribute::new("owner_recieved", self.fee.to_string()),
+            Attribute::new("sender_charged", self.sender_charged.to_string()),
+        ]
+    }
+}
+
+impl From<SplitTransferEvent> for Event {
+    fn from(event: SplitTransferEvent) -> Self {
+        let e = Event::new(SplitTransferEvent::TYPE).add_attributes(vec![
+            ("transfer_id", event.transfer_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("recipient_1", event.recipient_1.to_string()),
+            ("recipient_2", event.recipient_2.to_string()),
+            ("owner", event.owner.to_string()),
+            ("recipient_1_received", event.recipient_amount.to_string()),
+            ("recipient_2_received", event.recipient_amount.to_string()),
+            ("owner_received", event.fee.to_string()),
+            ("sender_charged", event.sender_charged.to_string()),
+            ("refund", event.refund.to_string()),
+        ]);
+        let e = match event.claim_deadline {
+            Some(claim_deadline) => e.add_attribute("claim_deadline", claim_deadline.to_string()),
+            None => e,
+        };
+        match event.vesting {
+            Some(vesting) => e.add_attributes(vec![
+                ("vesting_start", vesting.start.to_string()),
+                ("vesting_end", vesting.end.to_string()),
+            ]),
+            None => e,
+        }
+    }
+}
+
+/// Emitted for every successful `Withdraw`, seen by indexers as `wasm-withdraw`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawEvent {
+    pub sender: Addr,
+    pub amount: Uint128,
+}
+
+impl WithdrawEvent {
+    pub const TYPE: &'static str = "withdraw";
+
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "withdraw"),
+            Attribute::new("sender", self.sender.as_str()),
+            Attribute::new("withdraw_amount", self.amount.to_string()),
+        ]
+    }
+}
+
+impl From<WithdrawEvent> for Event {
+    fn from(event: WithdrawEvent) -> Self {
+        Event::new(WithdrawEvent::TYPE).add_attributes(vec![
+            ("sender", event.sender.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted once per `BatchTransfer`, after the `split_transfer` event of each
+/// item. Seen by indexers as `wasm-batch_transfer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchTransferEvent {
+    pub sender: Addr,
+    pub count: u64,
+    pub total_amount: Uint128,
+    pub total_fee: Uint128,
+    pub refund: Uint128,
+}
+
+impl BatchTransferEvent {
+    pub const TYPE: &'static str = "batch_transfer";
+}
+
+impl From<BatchTransferEvent> for Event {
+    fn from(event: BatchTransferEvent) -> Self {
+        Event::new(BatchTransferEvent::TYPE).add_attributes(vec![
+            ("sender", event.sender.to_string()),
+            ("count", event.count.to_string()),
+            ("total_amount", event.total_amount.to_string()),
+            ("total_fee", event.total_fee.to_string()),
+            ("refund", event.refund.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `PayIntoSplit`, seen by indexers as `wasm-split_payment`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitPaymentEvent {
+    pub split_id: u64,
+    pub sender: Addr,
+    pub fee: Uint128,
+    pub refund: Uint128,
+    /// Nested splits are shown as `split:<id>`; their own division follows in
+    /// `split_distribution` events.
+    pub credits: Vec<(SplitPayee, Uint128)>,
+}
+
+impl SplitPaymentEvent {
+    pub const TYPE: &'static str = "split_payment";
+}
+
+impl From<SplitPaymentEvent> for Event {
+    fn from(event: SplitPaymentEvent) -> Self {
+        let mut attributes = vec![
+            Attribute::new("split_id", event.split_id.to_string()),
+            Attribute::new("sender", event.sender.as_str()),
+            Attribute::new("fee", event.fee.to_string()),
+            Attribute::new("refund", event.refund.to_string()),
+        ];
+        attributes.extend(credit_attributes(&event.credits));
+        Event::new(SplitPaymentEvent::TYPE).add_attributes(attributes)
+    }
+}
+
+/// Emitted for each nested split a `PayIntoSplit` passes through, parents
+/// before children, so the full distribution tree can be rebuilt from the
+/// `wasm-split_distribution` events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitDistributionEvent {
+    pub split_id: u64,
+    pub parent_split_id: u64,
+    pub depth: u32,
+    pub amount: Uint128,
+    pub credits: Vec<(SplitPayee, Uint128)>,
+}
+
+impl SplitDistributionEvent {
+    pub const TYPE: &'static str = "split_distribution";
+}
+
+impl From<SplitDistributionEvent> for Event {
+    fn from(event: SplitDistributionEvent) -> Self {
+        let mut attributes = vec![
+            Attribute::new("split_id", event.split_id.to_string()),
+            Attribute::new("parent_split_id", event.parent_split_id.to_string()),
+            Attribute::new("depth", event.depth.to_string()),
+            Attribute::new("amount", event.amount.to_string()),
+        ];
+        attributes.extend(credit_attributes(&event.credits));
+        Event::new(SplitDistributionEvent::TYPE).add_attributes(attributes)
+    }
+}
+
+/// Recipients are numbered in the order they appear in the split.
+fn credit_attributes(credits: &[(SplitPayee, Uint128)]) -> Vec<Attribute> {
+    let mut attributes = vec![];
+    for (i, (payee, amount)) in credits.iter().enumerate() {
+        attributes.push(Attribute::new(format!("recipient_{}", i + 1), payee.to_string()));
+        attributes.push(Attribute::new(
+            format!("recipient_{}_received", i + 1),
+            amount.to_string(),
+        ));
+    }
+    attributes
+}
+
+/// Emitted for every `InternalTransfer`, seen by indexers as
+/// `wasm-internal_transfer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InternalTransferEvent {
+    pub sender: Addr,
+    pub recipient: Addr,
+    pub owner: Addr,
+    pub debited: Uint128,
+    pub credited: Uint128,
+    pub fee: Uint128,
+}
+
+impl InternalTransferEvent {
+    pub const TYPE: &'static str = "internal_transfer";
+}
+
+impl From<InternalTransferEvent> for Event {
+    fn from(event: InternalTransferEvent) -> Self {
+        Event::new(InternalTransferEvent::TYPE).add_attributes(vec![
+            ("sender", event.sender.to_string()),
+            ("recipient", event.recipient.to_string()),
+            ("owner", event.owner.to_string()),
+            ("debited", event.debited.to_string()),
+            ("credited", event.credited.to_string()),
+            ("fee", event.fee.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `WithdrawFrom`, seen by indexers as `wasm-withdraw_from`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithdrawFromEvent {
+    pub owner: Addr,
+    pub spender: Addr,
+    pub recipient: Addr,
+    pub amount: Uint128,
+}
+
+impl WithdrawFromEvent {
+    pub const TYPE: &'static str = "withdraw_from";
+}
+
+impl From<WithdrawFromEvent> for Event {
+    fn from(event: WithdrawFromEvent) -> Self {
+        Event::new(WithdrawFromEvent::TYPE).add_attributes(vec![
+            ("owner", event.owner.to_string()),
+            ("spender", event.spender.to_string()),
+            ("recipient", event.recipient.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `WithdrawIbc`, seen by indexers as `wasm-ibc_withdraw`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IbcWithdrawEvent {
+    pub sender: Addr,
+    pub channel_id: String,
+    pub remote_address: String,
+    pub amount: Uint128,
+}
+
+impl IbcWithdrawEvent {
+    pub const TYPE: &'static str = "ibc_withdraw";
+}
+
+impl From<IbcWithdrawEvent> for Event {
+    fn from(event: IbcWithdrawEvent) -> Self {
+        Event::new(IbcWithdrawEvent::TYPE).add_attributes(vec![
+            ("sender", event.sender.to_string()),
+            ("channel_id", event.channel_id),
+            ("remote_address", event.remote_address),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted when an IBC withdrawal times out or is rejected and the amount is
+/// credited back. Seen by indexers as `wasm-ibc_refund`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IbcRefundEvent {
+    pub sender: Addr,
+    pub channel_id: String,
+    pub sequence: u64,
+    pub amount: Uint128,
+    pub reason: String,
+}
+
+impl IbcRefundEvent {
+    pub const TYPE: &'static str = "ibc_refund";
+}
+
+impl From<IbcRefundEvent> for Event {
+    fn from(event: IbcRefundEvent) -> Self {
+        Event::new(IbcRefundEvent::TYPE).add_attributes(vec![
+            ("sender", event.sender.to_string()),
+            ("channel_id", event.channel_id),
+            ("sequence", event.sequence.to_string()),
+            ("amount", event.amount.to_string()),
+            ("reason", event.reason),
+        ])
+    }
+}
+
+/// Emitted when a credit is paid out under the recipient's forwarding
+/// preference instead of being kept in the contract. Seen by indexers as
+/// `wasm-forward`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardEvent {
+    pub recipient: Addr,
+    pub to: Addr,
+    pub amount: Uint128,
+}
+
+impl ForwardEvent {
+    pub const TYPE: &'static str = "forward";
+}
+
+impl From<ForwardEvent> for Event {
+    fn from(event: ForwardEvent) -> Self {
+        Event::new(ForwardEvent::TYPE).add_attributes(vec![
+            ("recipient", event.recipient.to_string()),
+            ("to", event.to.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted when a recipient claims their share of a transfer, seen by
+/// indexers as `wasm-claim`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimEvent {
+    pub transfer_id: u64,
+    pub recipient: Addr,
+    pub amount: Uint128,
+}
+
+impl ClaimEvent {
+    pub const TYPE: &'static str = "claim";
+}
+
+impl From<ClaimEvent> for Event {
+    fn from(event: ClaimEvent) -> Self {
+        Event::new(ClaimEvent::TYPE).add_attributes(vec![
+            ("transfer_id", event.transfer_id.to_string()),
+            ("recipient", event.recipient.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted when a sender takes back expired claims, seen by indexers as
+/// `wasm-reclaim`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReclaimEvent {
+    pub transfer_id: u64,
+    pub sender: Addr,
+    /// Expired claims, sent back to the sender.
+    pub amount: Uint128,
+    /// Unaccepted shares, returned to the sender's balance.
+    pub credited: Uint128,
+}
+
+impl ReclaimEvent {
+    pub const TYPE: &'static str = "reclaim";
+}
+
+impl From<ReclaimEvent> for Event {
+    fn from(event: ReclaimEvent) -> Self {
+        Event::new(ReclaimEvent::TYPE).add_attributes(vec![
+            ("transfer_id", event.transfer_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("amount", event.amount.to_string()),
+            ("credited", event.credited.to_string()),
+        ])
+    }
+}
+
+/// Emitted when a recipient accepts or rejects a pending transfer, seen by
+/// indexers as `wasm-accept` or `wasm-reject`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptanceEvent {
+    pub transfer_id: u64,
+    pub sender: Addr,
+    pub recipient: Addr,
+    pub amount: Uint128,
+    pub accepted: bool,
+}
+
+impl AcceptanceEvent {
+    pub const ACCEPT_TYPE: &'static str = "accept";
+    pub const REJECT_TYPE: &'static str = "reject";
+}
+
+impl From<AcceptanceEvent> for Event {
+    fn from(event: AcceptanceEvent) -> Self {
+        let ty = match event.accepted {
+            true => AcceptanceEvent::ACCEPT_TYPE,
+            false => AcceptanceEvent::REJECT_TYPE,
+        };
+        Event::new(ty).add_attributes(vec![
+            ("transfer_id", event.transfer_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("recipient", event.recipient.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `CancelTransfer`, seen by indexers as
+/// `wasm-cancel_transfer`. A recipient's `reversed` falls short of what it was
+/// `credited` by whatever it had already spent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CancelTransferEvent {
+    pub transfer_id: u64,
+    pub sender: Addr,
+    /// (recipient, credited, reversed)
+    pub recipients: Vec<(Addr, Uint128, Uint128)>,
+    pub fee_refunded: Uint128,
+    /// Total sent back to the sender.
+    pub amount: Uint128,
+}
+
+impl CancelTransferEvent {
+    pub const TYPE: &'static str = "cancel_transfer";
+}
+
+impl From<CancelTransferEvent> for Event {
+    fn from(event: CancelTransferEvent) -> Self {
+        let mut attributes = vec![
+            Attribute::new("transfer_id", event.transfer_id.to_string()),
+            Attribute::new("sender", event.sender.as_str()),
+        ];
+        for (i, (recipient, credited, reversed)) in event.recipients.iter().enumerate() {
+            attributes.push(Attribute::new(format!("recipient_{}", i + 1), recipient.as_str()));
+            attributes.push(Attribute::new(
+                format!("recipient_{}_credited", i + 1),
+                credited.to_string(),
+            ));
+            attributes.push(Attribute::new(
+                format!("recipient_{}_reversed", i + 1),
+                reversed.to_string(),
+            ));
+        }
+        attributes.push(Attribute::new("fee_refunded", event.fee_refunded.to_string()));
+        attributes.push(Attribute::new("amount", event.amount.to_string()));
+        Event::new(CancelTransferEvent::TYPE).add_attributes(attributes)
+    }
+}
+
+/// Emitted when a stream is created or topped up, seen by indexers as
+/// `wasm-stream_funded`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamFundedEvent {
+    pub stream_id: u64,
+    pub sender: Addr,
+    pub amount: Uint128,
+    pub end: Timestamp,
+}
+
+impl StreamFundedEvent {
+    pub const TYPE: &'static str = "stream_funded";
+}
+
+impl From<StreamFundedEvent> for Event {
+    fn from(event: StreamFundedEvent) -> Self {
+        Event::new(StreamFundedEvent::TYPE).add_attributes(vec![
+            ("stream_id", event.stream_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("amount", event.amount.to_string()),
+            ("end", event.end.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `CancelStream`, seen by indexers as
+/// `wasm-cancel_stream`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CancelStreamEvent {
+    pub stream_id: u64,
+    pub sender: Addr,
+    pub refund: Uint128,
+}
+
+impl CancelStreamEvent {
+    pub const TYPE: &'static str = "cancel_stream";
+}
+
+impl From<CancelStreamEvent> for Event {
+    fn from(event: CancelStreamEvent) -> Self {
+        Event::new(CancelStreamEvent::TYPE).add_attributes(vec![
+            ("stream_id", event.stream_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("refund", event.refund.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `CreateSchedule`, seen by indexers as
+/// `wasm-schedule_created`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleCreatedEvent {
+    pub schedule_id: u64,
+    pub sender: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    /// Charged per instalment.
+    pub amount: Uint128,
+    pub count: u32,
+    pub bounty: Uint128,
+}
+
+impl ScheduleCreatedEvent {
+    pub const TYPE: &'static str = "schedule_created";
+}
+
+impl From<ScheduleCreatedEvent> for Event {
+    fn from(event: ScheduleCreatedEvent) -> Self {
+        Event::new(ScheduleCreatedEvent::TYPE).add_attributes(vec![
+            ("schedule_id", event.schedule_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("recipient_1", event.recipient_1.to_string()),
+            ("recipient_2", event.recipient_2.to_string()),
+            ("amount", event.amount.to_string()),
+            ("count", event.count.to_string()),
+            ("bounty", event.bounty.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `CancelSchedule`, seen by indexers as
+/// `wasm-cancel_schedule`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CancelScheduleEvent {
+    pub schedule_id: u64,
+    pub sender: Addr,
+    pub refund: Uint128,
+}
+
+impl CancelScheduleEvent {
+    pub const TYPE: &'static str = "cancel_schedule";
+}
+
+impl From<CancelScheduleEvent> for Event {
+    fn from(event: CancelScheduleEvent) -> Self {
+        Event::new(CancelScheduleEvent::TYPE).add_attributes(vec![
+            ("schedule_id", event.schedule_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("refund", event.refund.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every instalment paid by `ExecuteDue`, after the instalment's
+/// own `split_transfer` event. Seen by indexers as `wasm-scheduled_transfer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledTransferEvent {
+    pub schedule_id: u64,
+    pub transfer_id: u64,
+    pub keeper: Addr,
+    pub bounty: Uint128,
+    pub remaining: u32,
+}
+
+impl ScheduledTransferEvent {
+    pub const TYPE: &'static str = "scheduled_transfer";
+}
+
+impl From<ScheduledTransferEvent> for Event {
+    fn from(event: ScheduledTransferEvent) -> Self {
+        Event::new(ScheduledTransferEvent::TYPE).add_attributes(vec![
+            ("schedule_id", event.schedule_id.to_string()),
+            ("transfer_id", event.transfer_id.to_string()),
+            ("keeper", event.keeper.to_string()),
+            ("bounty", event.bounty.to_string()),
+            ("remaining", event.remaining.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `HashLockedTransfer`, seen by indexers as
+/// `wasm-hash_lock_created`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashLockCreatedEvent {
+    pub lock_id: u64,
+    pub sender: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    /// Held for each recipient until the preimage is revealed.
+    pub recipient_amount: Uint128,
+    pub fee: Uint128,
+    pub hash: String,
+    pub expires: Expiration,
+}
+
+impl HashLockCreatedEvent {
+    pub const TYPE: &'static str = "hash_lock_created";
+}
+
+impl From<HashLockCreatedEvent> for Event {
+    fn from(event: HashLockCreatedEvent) -> Self {
+        Event::new(HashLockCreatedEvent::TYPE).add_attributes(vec![
+            ("lock_id", event.lock_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("recipient_1", event.recipient_1.to_string()),
+            ("recipient_2", event.recipient_2.to_string()),
+            ("recipient_amount", event.recipient_amount.to_string()),
+            ("fee", event.fee.to_string()),
+            ("hash", event.hash),
+            ("expires", event.expires.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `RefundHashLock`, seen by indexers as
+/// `wasm-refund_hash_lock`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefundHashLockEvent {
+    pub lock_id: u64,
+    pub sender: Addr,
+    pub amount: Uint128,
+}
+
+impl RefundHashLockEvent {
+    pub const TYPE: &'static str = "refund_hash_lock";
+}
+
+impl From<RefundHashLockEvent> for Event {
+    fn from(event: RefundHashLockEvent) -> Self {
+        Event::new(RefundHashLockEvent::TYPE).add_attributes(vec![
+            ("lock_id", event.lock_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted when a hash lock is released, after the `split_transfer` event of
+/// the transfer it became. Carries the preimage so the other side of a swap
+/// can use it. Seen by indexers as `wasm-hash_lock_released`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashLockReleasedEvent {
+    pub lock_id: u64,
+    pub transfer_id: u64,
+    pub preimage: String,
+}
+
+impl HashLockReleasedEvent {
+    pub const TYPE: &'static str = "hash_lock_released";
+}
+
+impl From<HashLockReleasedEvent> for Event {
+    fn from(event: HashLockReleasedEvent) -> Self {
+        Event::new(HashLockReleasedEvent::TYPE).add_attributes(vec![
+            ("lock_id", event.lock_id.to_string()),
+            ("transfer_id", event.transfer_id.to_string()),
+            ("preimage", event.preimage),
+        ])
+    }
+}
+
+/// Emitted for every `CreateEscrow`, seen by indexers as
+/// `wasm-escrow_created`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscrowCreatedEvent {
+    pub escrow_id: u64,
+    pub sender: Addr,
+    pub arbiter: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    pub joint_approval: bool,
+    /// Held for release, after the fee.
+    pub amount: Uint128,
+    pub fee: Uint128,
+}
+
+impl EscrowCreatedEvent {
+    pub const TYPE: &'static str = "escrow_created";
+}
+
+impl From<EscrowCreatedEvent> for Event {
+    fn from(event: EscrowCreatedEvent) -> Self {
+        Event::new(EscrowCreatedEvent::TYPE).add_attributes(vec![
+            ("escrow_id", event.escrow_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("arbiter", event.arbiter.to_string()),
+            ("recipient_1", event.recipient_1.to_string()),
+            ("recipient_2", event.recipient_2.to_string()),
+            ("joint_approval", event.joint_approval.to_string()),
+            ("amount", event.amount.to_string()),
+            ("fee", event.fee.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every release from an escrow, seen by indexers as
+/// `wasm-escrow_release`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscrowReleaseEvent {
+    pub escrow_id: u64,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    /// Credited to each recipient.
+    pub recipient_amount: Uint128,
+    pub remaining: Uint128,
+}
+
+impl EscrowReleaseEvent {
+    pub const TYPE: &'static str = "escrow_release";
+}
+
+impl From<EscrowReleaseEvent> for Event {
+    fn from(event: EscrowReleaseEvent) -> Self {
+        Event::new(EscrowReleaseEvent::TYPE).add_attributes(vec![
+            ("escrow_id", event.escrow_id.to_string()),
+            ("recipient_1", event.recipient_1.to_string()),
+            ("recipient_2", event.recipient_2.to_string()),
+            ("recipient_amount", event.recipient_amount.to_string()),
+            ("remaining", event.remaining.to_string()),
+        ])
+    }
+}
+
+/// Emitted when one party approves a jointly approved release that still
+/// waits on the other, seen by indexers as `wasm-approve_release`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApproveReleaseEvent {
+    pub escrow_id: u64,
+    pub approved_by: Addr,
+    pub amount: Uint128,
+}
+
+impl ApproveReleaseEvent {
+    pub const TYPE: &'static str = "approve_release";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "approve_release"),
+            Attribute::new("escrow_id", self.escrow_id.to_string()),
+            Attribute::new("amount", self.amount.to_string()),
+        ]
+    }
+}
+
+impl From<ApproveReleaseEvent> for Event {
+    fn from(event: ApproveReleaseEvent) -> Self {
+        Event::new(ApproveReleaseEvent::TYPE).add_attributes(vec![
+            ("escrow_id", event.escrow_id.to_string()),
+            ("approved_by", event.approved_by.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `RefundEscrow`, seen by indexers as
+/// `wasm-refund_escrow`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefundEscrowEvent {
+    pub escrow_id: u64,
+    pub sender: Addr,
+    pub refund: Uint128,
+}
+
+impl RefundEscrowEvent {
+    pub const TYPE: &'static str = "refund_escrow";
+}
+
+impl From<RefundEscrowEvent> for Event {
+    fn from(event: RefundEscrowEvent) -> Self {
+        Event::new(RefundEscrowEvent::TYPE).add_attributes(vec![
+            ("escrow_id", event.escrow_id.to_string()),
+            ("sender", event.sender.to_string()),
+            ("refund", event.refund.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `CreateInvoice`, seen by indexers as
+/// `wasm-invoice_created`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceCreatedEvent {
+    pub invoice_id: u64,
+    pub creator: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    pub amount: Uint128,
+    pub expires: Expiration,
+}
+
+impl InvoiceCreatedEvent {
+    pub const TYPE: &'static str = "invoice_created";
+}
+
+impl From<InvoiceCreatedEvent> for Event {
+    fn from(event: InvoiceCreatedEvent) -> Self {
+        Event::new(InvoiceCreatedEvent::TYPE).add_attributes(vec![
+            ("invoice_id", event.invoice_id.to_string()),
+            ("creator", event.creator.to_string()),
+            ("recipient_1", event.recipient_1.to_string()),
+            ("recipient_2", event.recipient_2.to_string()),
+            ("amount", event.amount.to_string()),
+            ("expires", event.expires.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `PayInvoice`, after the `split_transfer` event of the
+/// transfer that paid it. Seen by indexers as `wasm-invoice_paid`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoicePaidEvent {
+    pub invoice_id: u64,
+    pub transfer_id: u64,
+    pub payer: Addr,
+}
+
+impl InvoicePaidEvent {
+    pub const TYPE: &'static str = "invoice_paid";
+}
+
+impl From<InvoicePaidEvent> for Event {
+    fn from(event: InvoicePaidEvent) -> Self {
+        Event::new(InvoicePaidEvent::TYPE).add_attributes(vec![
+            ("invoice_id", event.invoice_id.to_string()),
+            ("transfer_id", event.transfer_id.to_string()),
+            ("payer", event.payer.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `CreateSplit`, seen by indexers as `wasm-split_created`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitCreatedEvent {
+    pub split_id: u64,
+    pub owner: Addr,
+    pub name: String,
+    pub recipients: u64,
+}
+
+impl SplitCreatedEvent {
+    pub const TYPE: &'static str = "split_created";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "create_split"),
+            Attribute::new("split_id", self.split_id.to_string()),
+            Attribute::new("owner", self.owner.as_str()),
+        ]
+    }
+}
+
+impl From<SplitCreatedEvent> for Event {
+    fn from(event: SplitCreatedEvent) -> Self {
+        Event::new(SplitCreatedEvent::TYPE).add_attributes(vec![
+            ("split_id", event.split_id.to_string()),
+            ("owner", event.owner.to_string()),
+            ("name", event.name),
+            ("recipients", event.recipients.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `UpdateSplit`, seen by indexers as `wasm-split_updated`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitUpdatedEvent {
+    pub split_id: u64,
+    pub name: String,
+    pub recipients: u64,
+}
+
+impl SplitUpdatedEvent {
+    pub const TYPE: &'static str = "split_updated";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "update_split"),
+            Attribute::new("split_id", self.split_id.to_string()),
+        ]
+    }
+}
+
+impl From<SplitUpdatedEvent> for Event {
+    fn from(event: SplitUpdatedEvent) -> Self {
+        Event::new(SplitUpdatedEvent::TYPE).add_attributes(vec![
+            ("split_id", event.split_id.to_string()),
+            ("name", event.name),
+            ("recipients", event.recipients.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `LockSplit`, seen by indexers as `wasm-split_locked`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitLockedEvent {
+    pub split_id: u64,
+}
+
+impl SplitLockedEvent {
+    pub const TYPE: &'static str = "split_locked";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "lock_split"),
+            Attribute::new("split_id", self.split_id.to_string()),
+        ]
+    }
+}
+
+impl From<SplitLockedEvent> for Event {
+    fn from(event: SplitLockedEvent) -> Self {
+        Event::new(SplitLockedEvent::TYPE)
+            .add_attribute("split_id", event.split_id.to_string())
+    }
+}
+
+/// Emitted for every `IncreaseAllowance` and `DecreaseAllowance`, seen by
+/// indexers as `wasm-increase_allowance` or `wasm-decrease_allowance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowanceEvent {
+    pub owner: Addr,
+    pub spender: Addr,
+    pub amount: Uint128,
+    pub increased: bool,
+}
+
+impl AllowanceEvent {
+    pub const INCREASE_TYPE: &'static str = "increase_allowance";
+    pub const DECREASE_TYPE: &'static str = "decrease_allowance";
+
+    fn ty(&self) -> &'static str {
+        match self.increased {
+            true => Self::INCREASE_TYPE,
+            false => Self::DECREASE_TYPE,
+        }
+    }
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", self.ty()),
+            Attribute::new("owner", self.owner.to_string()),
+            Attribute::new("spender", self.spender.to_string()),
+            Attribute::new("amount", self.amount.to_string()),
+        ]
+    }
+}
+
+impl From<AllowanceEvent> for Event {
+    fn from(event: AllowanceEvent) -> Self {
+        Event::new(event.ty()).add_attributes(vec![
+            ("owner", event.owner.to_string()),
+            ("spender", event.spender.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `SetForwarding`, seen by indexers as
+/// `wasm-set_forwarding`, or `wasm-clear_forwarding` when `forwarding` is
+/// `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardingEvent {
+    pub address: Addr,
+    pub forwarding: Option<Forwarding>,
+}
+
+impl ForwardingEvent {
+    pub const SET_TYPE: &'static str = "set_forwarding";
+    pub const CLEAR_TYPE: &'static str = "clear_forwarding";
+
+    fn ty(&self) -> &'static str {
+        match self.forwarding {
+            Some(_) => Self::SET_TYPE,
+            None => Self::CLEAR_TYPE,
+        }
+    }
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", self.ty()),
+            Attribute::new("address", self.address.to_string()),
+        ]
+    }
+}
+
+impl From<ForwardingEvent> for Event {
+    fn from(event: ForwardingEvent) -> Self {
+        let ty = event.ty();
+        let event_out = Event::new(ty).add_attribute("address", event.address.to_string());
+        match event.forwarding {
+            None => event_out,
+            Some(Forwarding::Withdraw) => event_out.add_attribute("mode", "withdraw"),
+            Some(Forwarding::ForwardTo(to)) => event_out
+                .add_attribute("mode", "forward_to")
+                .add_attribute("forward_to", to.to_string()),
+            Some(Forwarding::Threshold(amount)) => event_out
+                .add_attribute("mode", "threshold")
+                .add_attribute("threshold", amount.to_string()),
+        }
+    }
+}
+
+/// Emitted for every `SetAcceptance`, seen by indexers as
+/// `wasm-set_acceptance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptanceWindowEvent {
+    pub address: Addr,
+    pub window: Option<Duration>,
+}
+
+impl AcceptanceWindowEvent {
+    pub const TYPE: &'static str = "set_acceptance";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "set_acceptance"),
+            Attribute::new("address", self.address.to_string()),
+            Attribute::new("required", self.window.is_some().to_string()),
+        ]
+    }
+}
+
+impl From<AcceptanceWindowEvent> for Event {
+    fn from(event: AcceptanceWindowEvent) -> Self {
+        let mut attributes = vec![
+            ("address", event.address.to_string()),
+            ("required", event.window.is_some().to_string()),
+        ];
+        if let Some(window) = event.window {
+            attributes.push(("window", window.to_string()));
+        }
+        Event::new(AcceptanceWindowEvent::TYPE).add_attributes(attributes)
+    }
+}
+
+/// Emitted for every `SetStaking`, seen by indexers as `wasm-set_staking`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetStakingEvent {
+    pub config: Option<StakingConfig>,
+}
+
+impl SetStakingEvent {
+    pub const TYPE: &'static str = "set_staking";
+
+    fn config_attributes(&self) -> Vec<Attribute> {
+        match &self.config {
+            Some(config) => vec![
+                Attribute::new("validator", &config.validator),
+                Attribute::new("buffer", config.buffer.to_string()),
+            ],
+            None => vec![],
+        }
+    }
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        let mut attributes = vec![Attribute::new("action", "set_staking")];
+        attributes.extend(self.config_attributes());
+        attributes
+    }
+}
+
+impl From<SetStakingEvent> for Event {
+    fn from(event: SetStakingEvent) -> Self {
+        Event::new(SetStakingEvent::TYPE)
+            .add_attribute("enabled", event.config.is_some().to_string())
+            .add_attributes(event.config_attributes())
+    }
+}
+
+/// Emitted for every `Rebalance`, seen by indexers as `wasm-rebalance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebalanceEvent {
+    pub delegated: Uint128,
+}
+
+impl RebalanceEvent {
+    pub const TYPE: &'static str = "rebalance";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "rebalance"),
+            Attribute::new("delegated", self.delegated.to_string()),
+        ]
+    }
+}
+
+impl From<RebalanceEvent> for Event {
+    fn from(event: RebalanceEvent) -> Self {
+        Event::new(RebalanceEvent::TYPE).add_attribute("delegated", event.delegated.to_string())
+    }
+}
+
+/// Emitted for every `ClaimWithdrawal`, seen by indexers as
+/// `wasm-claim_withdrawal`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimWithdrawalEvent {
+    pub recipient: Addr,
+    pub amount: Uint128,
+}
+
+impl ClaimWithdrawalEvent {
+    pub const TYPE: &'static str = "claim_withdrawal";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "claim_withdrawal"),
+            Attribute::new("amount", self.amount.to_string()),
+        ]
+    }
+}
+
+impl From<ClaimWithdrawalEvent> for Event {
+    fn from(event: ClaimWithdrawalEvent) -> Self {
+        Event::new(ClaimWithdrawalEvent::TYPE).add_attributes(vec![
+            ("recipient", event.recipient.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `HarvestRewards`, seen by indexers as
+/// `wasm-harvest_rewards`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HarvestRewardsEvent {
+    pub validator: String,
+    pub rewards: Uint128,
+}
+
+impl HarvestRewardsEvent {
+    pub const TYPE: &'static str = "harvest_rewards";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "harvest_rewards"),
+            Attribute::new("rewards", self.rewards.to_string()),
+        ]
+    }
+}
+
+impl From<HarvestRewardsEvent> for Event {
+    fn from(event: HarvestRewardsEvent) -> Self {
+        Event::new(HarvestRewardsEvent::TYPE).add_attributes(vec![
+            ("validator", event.validator),
+            ("rewards", event.rewards.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `ClaimRewards`, seen by indexers as
+/// `wasm-claim_rewards`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimRewardsEvent {
+    pub recipient: Addr,
+    pub amount: Uint128,
+}
+
+impl ClaimRewardsEvent {
+    pub const TYPE: &'static str = "claim_rewards";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "claim_rewards"),
+            Attribute::new("amount", self.amount.to_string()),
+        ]
+    }
+}
+
+impl From<ClaimRewardsEvent> for Event {
+    fn from(event: ClaimRewardsEvent) -> Self {
+        Event::new(ClaimRewardsEvent::TYPE).add_attributes(vec![
+            ("recipient", event.recipient.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `DelegateBalance` and `UndelegateBalance`, seen by
+/// indexers as `wasm-delegate_balance` or `wasm-undelegate_balance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelegationEvent {
+    pub delegator: Addr,
+    pub validator: String,
+    pub amount: Uint128,
+    pub delegated: bool,
+}
+
+impl DelegationEvent {
+    pub const DELEGATE_TYPE: &'static str = "delegate_balance";
+    pub const UNDELEGATE_TYPE: &'static str = "undelegate_balance";
+
+    fn ty(&self) -> &'static str {
+        match self.delegated {
+            true => Self::DELEGATE_TYPE,
+            false => Self::UNDELEGATE_TYPE,
+        }
+    }
+
+    fn attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("delegator", self.delegator.to_string()),
+            Attribute::new("validator", &self.validator),
+            Attribute::new("amount", self.amount.to_string()),
+        ]
+    }
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        let mut attributes = vec![Attribute::new("action", self.ty())];
+        attributes.extend(self.attributes());
+        attributes
+    }
+}
+
+impl From<DelegationEvent> for Event {
+    fn from(event: DelegationEvent) -> Self {
+        Event::new(event.ty()).add_attributes(event.attributes())
+    }
+}
+
+/// Emitted for every `RedelegateBalance`, seen by indexers as
+/// `wasm-redelegate_balance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedelegationEvent {
+    pub delegator: Addr,
+    pub src_validator: String,
+    pub dst_validator: String,
+    pub amount: Uint128,
+}
+
+impl RedelegationEvent {
+    pub const TYPE: &'static str = "redelegate_balance";
+
+    fn attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("delegator", self.delegator.to_string()),
+            Attribute::new("src_validator", &self.src_validator),
+            Attribute::new("dst_validator", &self.dst_validator),
+            Attribute::new("amount", self.amount.to_string()),
+        ]
+    }
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        let mut attributes = vec![Attribute::new("action", "redelegate_balance")];
+        attributes.extend(self.attributes());
+        attributes
+    }
+}
+
+impl From<RedelegationEvent> for Event {
+    fn from(event: RedelegationEvent) -> Self {
+        Event::new(RedelegationEvent::TYPE).add_attributes(event.attributes())
+    }
+}
+
+/// Emitted for every `FundRemoteEscrow`, seen by indexers as
+/// `wasm-fund_remote_escrow`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundRemoteEscrowEvent {
+    pub channel_id: String,
+    pub remote_sender: String,
+    pub funder: Addr,
+    pub amount: Uint128,
+    pub escrowed: Uint128,
+}
+
+impl FundRemoteEscrowEvent {
+    pub const TYPE: &'static str = "fund_remote_escrow";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "fund_remote_escrow"),
+            Attribute::new("channel_id", &self.channel_id),
+            Attribute::new("remote_sender", &self.remote_sender),
+            Attribute::new("amount", self.amount.to_string()),
+            Attribute::new("escrowed", self.escrowed.to_string()),
+        ]
+    }
+}
+
+impl From<FundRemoteEscrowEvent> for Event {
+    fn from(event: FundRemoteEscrowEvent) -> Self {
+        Event::new(FundRemoteEscrowEvent::TYPE).add_attributes(vec![
+            ("channel_id", event.channel_id),
+            ("remote_sender", event.remote_sender),
+            ("funder", event.funder.to_string()),
+            ("amount", event.amount.to_string()),
+            ("escrowed", event.escrowed.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `RefundRemoteEscrow`, seen by indexers as
+/// `wasm-refund_remote_escrow`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefundRemoteEscrowEvent {
+    pub channel_id: String,
+    pub remote_sender: String,
+    pub funder: Addr,
+    pub amount: Uint128,
+}
+
+impl RefundRemoteEscrowEvent {
+    pub const TYPE: &'static str = "refund_remote_escrow";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "refund_remote_escrow"),
+            Attribute::new("channel_id", &self.channel_id),
+            Attribute::new("remote_sender", &self.remote_sender),
+            Attribute::new("amount", self.amount.to_string()),
+        ]
+    }
+}
+
+impl From<RefundRemoteEscrowEvent> for Event {
+    fn from(event: RefundRemoteEscrowEvent) -> Self {
+        Event::new(RefundRemoteEscrowEvent::TYPE).add_attributes(vec![
+            ("channel_id", event.channel_id),
+            ("remote_sender", event.remote_sender),
+            ("funder", event.funder.to_string()),
+            ("amount", event.amount.to_string()),
+        ])
+    }
+}
+
+/// Emitted for every `SetIbcCounterparty`, seen by indexers as
+/// `wasm-set_ibc_counterparty`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetIbcCounterpartyEvent {
+    pub port_id: String,
+}
+
+impl SetIbcCounterpartyEvent {
+    pub const TYPE: &'static str = "set_ibc_counterparty";
+
+    /// The flat attributes emitted before this event existed.
+    pub fn legacy_attributes(&self) -> Vec<Attribute> {
+        vec![
+            Attribute::new("action", "set_ibc_counterparty"),
+            Attribute::new("port_id", &self.port_id),
+        ]
+    }
+}
+
+impl From<SetIbcCounterpartyEvent> for Event {
+    fn from(event: SetIbcCounterpartyEvent) -> Self {
+        Event::new(SetIbcCounterpartyEvent::TYPE).add_attribute("port_id", event.port_id)
+    }
+}
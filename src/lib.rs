@@ -1,4 +1,5 @@
 pub mod contract;
+pub mod events;
 pub mod msg;
 pub mod state;
 pub use crate::error::ContractError;
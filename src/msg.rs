@@ -1,19 +1,171 @@
 use serde::{Deserialize, Serialize};
-use cosmwasm_std::{Uint128};
+use cosmwasm_std::{Binary, Timestamp, Uint128};
+use cw_utils::{Duration, Expiration};
+
+use crate::state::{
+    Forwarding, HashLock, PendingClaim, Split, StakingConfig, Stream, TransferRecord,
+};
 
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct InstantiateMsg {
     pub owner: String,
     pub coin_denom : String,
-    pub fee : Uint128
+    pub fee : Uint128,
+    /// Also emit the pre-event flat attributes (including their original
+    /// key spellings) for indexers that have not moved to typed events.
+    pub legacy_attributes: Option<bool>,
+    /// How `BatchTransfer` charges the fee. Defaults to once per item.
+    pub batch_fee_mode: Option<BatchFeeMode>,
+    /// How `InternalTransfer` and `InternalSplit` charge the fee. Defaults
+    /// to no fee.
+    pub internal_fee_mode: Option<InternalFeeMode>,
+    /// Whether `CancelTransfer` gives the fee back. Defaults to keeping it.
+    pub cancel_fee_mode: Option<CancelFeeMode>,
+    /// Paid to whoever executes a due instalment of a schedule. Defaults to
+    /// nothing.
+    pub keeper_bounty: Option<Uint128>,
+    /// Whether a failing `SplitReceived` hook fails the transfer. Defaults
+    /// to ignoring the failure.
+    pub hook_failure_mode: Option<HookFailureMode>,
+    /// How long the chain takes to unbond, after which queued withdrawals
+    /// can be claimed. Defaults to 21 days.
+    pub unbonding_period: Option<Duration>,
+    /// Port of the counterpart contract on the other chain. Channels from
+    /// any other port are refused, so none can be opened until it is set.
+    pub ibc_counterparty_port: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum BatchFeeMode {
+    #[default]
+    PerItem,
+    PerBatch,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum InternalFeeMode {
+    /// Internal moves are not charged.
+    #[default]
+    Free,
+    /// The fee comes out of the amount moved.
+    Deducted,
+    /// The fee is debited from the sender on top of the amount moved.
+    OnTop,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum CancelFeeMode {
+    /// The owner keeps the fee of a cancelled transfer.
+    #[default]
+    Retain,
+    /// The fee is taken back from the owner's balance and returned too.
+    Refund,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum HookFailureMode {
+    /// The transfer stands and the error is recorded in a `hook_error`
+    /// attribute.
+    #[default]
+    Ignore,
+    /// The whole transfer fails with the hook.
+    Revert,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum QueryMsg {
     Owner {},
-    Balance {address : String},
+    /// Balance of `address`, or its balance at the start of block `height`.
+    Balance {address : String, height: Option<u64>},
     Fee {},
+    /// Transfers sent by `address`, oldest first.
+    TransfersBySender {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Transfers where `address` is either recipient, oldest first.
+    TransfersByRecipient {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// What a `Transfer` of `transfer_amount` split evenly between
+    /// `recipients` would do, assuming exactly `transfer_amount` is attached.
+    /// Transfers take exactly two recipients.
+    SimulateTransfer {
+        transfer_amount: Uint128,
+        recipients: Vec<String>,
+    },
+    /// A single split, returned as `Split`.
+    Split { split_id: u64 },
+    /// Splits by id, optionally only those owned by `owner`.
+    Splits {
+        owner: Option<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// How much `spender` may still withdraw from `owner`'s balance.
+    Allowance { owner: String, spender: String },
+    /// Every allowance granted by `owner`, ordered by spender.
+    AllAllowances {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// The forwarding preference set by `address`, if any.
+    Forwarding { address: String },
+    /// Unexpired claims waiting for `recipient`, by transfer id.
+    PendingClaims {
+        recipient: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Vested, withdrawn and locked amounts across `recipient`'s vesting
+    /// transfers.
+    Vesting { recipient: String },
+    /// A stream and how much of it has streamed so far, as `StreamResp`.
+    Stream { stream_id: u64 },
+    /// A single hash lock, returned as `HashLock`.
+    HashLock { lock_id: u64 },
+    /// Open hash locks by id.
+    HashLocks {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// A single escrow, returned as `Escrow`.
+    Escrow { escrow_id: u64 },
+    /// A single invoice, returned as `Invoice`.
+    Invoice { invoice_id: u64 },
+    /// The staking config and how much is delegated and queued.
+    Staking {},
+    /// Withdrawals owed to `address` once funds finish unbonding.
+    QueuedWithdrawal { address: String },
+    /// What `address` has delegated with `DelegateBalance`, by validator.
+    Delegations { address: String },
+    /// Funds escrowed for `remote_sender` on `channel_id`.
+    RemoteEscrow {
+        channel_id: String,
+        remote_sender: String,
+    },
+    /// Harvested rewards `address` has yet to claim, including those booked
+    /// to its own delegations.
+    PendingRewards { address: String },
+    /// A single schedule, returned as `Schedule`.
+    Schedule { schedule_id: u64 },
+    /// Transfers waiting for `recipient` to accept them, by transfer id.
+    PendingAcceptances {
+        recipient: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Expired claims on transfers by `sender`, which it can reclaim.
+    ExpiredClaims {
+        sender: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -30,6 +182,114 @@ pub struct BalanceResp {
     pub balance: Uint128,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TransfersResp {
+    pub transfers: Vec<TransferRecord>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SplitsResp {
+    pub splits: Vec<Split>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct AllowanceResp {
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SpenderAllowance {
+    pub spender: String,
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct AllAllowancesResp {
+    pub allowances: Vec<SpenderAllowance>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct VestingResp {
+    pub total: Uint128,
+    pub vested: Uint128,
+    /// Vested amounts already moved into the balance, from where they can be
+    /// withdrawn.
+    pub withdrawn: Uint128,
+    pub locked: Uint128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct StreamResp {
+    pub stream: Stream,
+    pub streamed: Uint128,
+    pub remaining: Uint128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HashLocksResp {
+    pub hash_locks: Vec<HashLock>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct StakingResp {
+    pub config: Option<StakingConfig>,
+    pub delegated: Uint128,
+    pub queued: Uint128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct QueuedWithdrawalResp {
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct UserDelegation {
+    pub validator: String,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DelegationsResp {
+    pub delegations: Vec<UserDelegation>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RemoteEscrowResp {
+    pub amount: Uint128,
+    pub funder: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PendingRewardsResp {
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ForwardingResp {
+    pub forwarding: Option<Forwarding>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ClaimsResp {
+    pub claims: Vec<PendingClaim>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RecipientAmount {
+    pub address: String,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SimulateTransferResp {
+    pub fee: Uint128,
+    pub recipients: Vec<RecipientAmount>,
+    pub sender_charged: Uint128,
+    pub refund: Uint128,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 
 pub enum ExecuteMsg {
@@ -40,5 +300,310 @@ pub enum ExecuteMsg {
         transfer_amount: Uint128,
         recipient_1: String,
         recipient_2: String,
+        /// Hold the recipients' shares as claims they must make before this
+        /// passes, after which the sender can `Reclaim` them.
+        claim_deadline: Option<Expiration>,
+        /// How long the sender has to `CancelTransfer`.
+        cancel_window: Option<Duration>,
+        /// Lock each recipient's share, releasing it to their balance as it
+        /// vests. Recipients that accept transfers or forward their credits
+        /// cannot be sent vesting transfers.
+        vesting: Option<Vesting>,
+        /// Passed to recipients that are contracts in a `SplitReceived` hook,
+        /// once their share is credited to them.
+        msg: Option<Binary>,
     },
+
+    /// Several transfers funded by one set of coins, applied all or nothing.
+    BatchTransfer { transfers: Vec<BatchTransferItem> },
+
+    /// Registers a reusable split owned by the sender.
+    CreateSplit {
+        name: String,
+        recipients: Vec<SplitRecipient>,
+    },
+    /// Replaces the recipients of an unlocked split. Owner only.
+    UpdateSplit {
+        split_id: u64,
+        name: Option<String>,
+        recipients: Vec<SplitRecipient>,
+    },
+    /// Permanently prevents further updates to a split. Owner only.
+    LockSplit { split_id: u64 },
+    /// Divides the attached funds, less the fee, according to a split.
+    PayIntoSplit { split_id: u64 },
+
+    /// Moves `amount` of the sender's balance to `to` without withdrawing.
+    InternalTransfer { to: String, amount: Uint128 },
+    /// A `Transfer` paid from the sender's balance rather than attached funds.
+    InternalSplit {
+        amount: Uint128,
+        recipient_1: String,
+        recipient_2: String,
+    },
+
+    /// Lets `spender` withdraw up to `amount` more of the sender's balance.
+    IncreaseAllowance {
+        spender: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    DecreaseAllowance {
+        spender: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    /// Withdraws from `owner`'s balance to `recipient` using an allowance.
+    WithdrawFrom {
+        owner: String,
+        amount: Uint128,
+        recipient: String,
+    },
+    /// How credits to the sender should be handled; `None` keeps them in the
+    /// contract as usual. Fees credited to the owner are never forwarded.
+    SetForwarding { preference: Option<ForwardPreference> },
+
+    /// Moves the sender's share of a transfer made with a claim deadline into
+    /// their balance.
+    Claim { transfer_id: u64 },
+    /// Returns the expired, unclaimed or unaccepted shares of a transfer to
+    /// its sender.
+    Reclaim { transfer_id: u64 },
+
+    /// Requires the sender to accept incoming transfers within `window`;
+    /// `None` goes back to crediting them straight away.
+    SetAcceptance { window: Option<Duration> },
+    /// Moves the sender's pending share of a transfer into their balance.
+    Accept { transfer_id: u64 },
+    /// Returns the sender's pending share of a transfer to the transfer's
+    /// sender.
+    Reject { transfer_id: u64 },
+
+    /// Reverses a transfer made with a cancel window, returning whatever the
+    /// recipients have not yet withdrawn.
+    CancelTransfer { transfer_id: u64 },
+
+    /// Streams `rate_per_second` to each recipient between `start` and `end`.
+    /// The attached funds must cover the whole stream plus the fee.
+    CreateStream {
+        recipients: Vec<String>,
+        rate_per_second: Uint128,
+        start: Timestamp,
+        end: Timestamp,
+    },
+    /// Extends a stream by as many whole seconds as the attached funds pay
+    /// for; the remainder is refunded. Only the stream's sender can do this.
+    TopUpStream { stream_id: u64 },
+    /// Ends a stream now, refunding whatever has not yet streamed.
+    CancelStream { stream_id: u64 },
+
+    /// Prefunds `count` transfers of `amount`, one due every `interval`
+    /// starting now. The attached funds must cover every instalment and its
+    /// keeper bounty.
+    CreateSchedule {
+        amount: Uint128,
+        recipient_1: String,
+        recipient_2: String,
+        interval: Duration,
+        count: u32,
+    },
+    /// Pays out the next instalment of a schedule if it is due. Anyone can
+    /// call this and receives the keeper bounty.
+    ExecuteDue { schedule_id: u64 },
+    /// Stops a schedule, refunding the instalments not yet paid.
+    CancelSchedule { schedule_id: u64 },
+
+    /// A `Transfer` escrowed until the preimage of `hash` (hex encoded
+    /// SHA-256) is revealed, refundable to the sender once `expires` passes.
+    HashLockedTransfer {
+        transfer_amount: Uint128,
+        recipient_1: String,
+        recipient_2: String,
+        hash: String,
+        expires: Expiration,
+    },
+    /// Releases a hash lock to its recipients. Anyone holding the hex
+    /// encoded preimage can call this.
+    RevealPreimage { lock_id: u64, preimage: String },
+    /// Returns an expired hash lock to its sender.
+    RefundHashLock { lock_id: u64 },
+
+    /// Holds the attached funds, less the fee, for the two recipients until
+    /// `arbiter` releases them. With `joint_approval` the sender must also
+    /// approve each release.
+    CreateEscrow {
+        recipient_1: String,
+        recipient_2: String,
+        arbiter: String,
+        joint_approval: bool,
+    },
+    /// Releases `amount` of an escrow, shared evenly between its recipients,
+    /// or the remainder when `None`. Under joint approval the first call
+    /// approves and the matching call from the other party releases.
+    ReleaseEscrow {
+        escrow_id: u64,
+        amount: Option<Uint128>,
+    },
+    /// Lets the arbiter return whatever is still held to the sender.
+    RefundEscrow { escrow_id: u64 },
+
+    /// Requests `amount` split between the sender and `co_recipient`, or
+    /// paid wholly to the sender when there is none. The new invoice id is
+    /// returned in the response data.
+    CreateInvoice {
+        amount: Uint128,
+        denom: String,
+        co_recipient: Option<String>,
+        expires: Expiration,
+    },
+    /// Settles an invoice; exactly its amount must be attached.
+    PayInvoice { invoice_id: u64 },
+
+    /// Owner only. Delegates held funds above a liquidity buffer; `None`
+    /// stops staking and undelegates everything.
+    SetStaking { config: Option<StakingConfig> },
+    /// Delegates whatever liquid funds sit above the buffer.
+    Rebalance {},
+    /// Pays out a withdrawal that was queued while funds unbonded.
+    ClaimWithdrawal {},
+    /// Withdraws staking rewards and shares them across all balances.
+    HarvestRewards {},
+    /// Pays out the sender's share of harvested rewards, along with what
+    /// their own delegations have earned.
+    ClaimRewards {},
+
+    /// Delegates part of the sender's balance to `validator` on their behalf.
+    DelegateBalance { validator: String, amount: Uint128 },
+    /// Unbonds a delegation made with `DelegateBalance`. The coins are queued
+    /// for `ClaimWithdrawal` once they arrive.
+    UndelegateBalance { validator: String, amount: Uint128 },
+    /// Moves a delegation made with `DelegateBalance` to another validator.
+    RedelegateBalance {
+        src_validator: String,
+        dst_validator: String,
+        amount: Uint128,
+    },
+
+    /// Withdraws to an address on another chain with an ICS20 transfer. The
+    /// balance is restored if the transfer times out or fails on arrival,
+    /// which the chain reports through ibc-hooks callbacks.
+    WithdrawIbc {
+        channel_id: String,
+        remote_address: String,
+        amount: Uint128,
+        timeout: Timestamp,
+    },
+    /// Escrows the attached coins for `remote_sender` on another chain, to be
+    /// split by packets it sends over `channel_id`.
+    FundRemoteEscrow {
+        channel_id: String,
+        remote_sender: String,
+    },
+    /// Funder only. Refunds what is left of a remote escrow once its channel
+    /// has closed.
+    RefundRemoteEscrow {
+        channel_id: String,
+        remote_sender: String,
+    },
+    /// Owner only. Sets the port of the counterpart contract, the only one
+    /// allowed to open a channel.
+    SetIbcCounterparty { port_id: String },
+    /// Contract only. Runs a split packet received on `channel_id`, as a
+    /// submessage of `ibc_packet_receive` so a failure reverts all of it.
+    ReceiveSplit {
+        channel_id: String,
+        packet: SplitPacket,
+    },
+}
+
+/// Executed on recipients that are contracts when a transfer carries a `msg`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum ReceiverMsg {
+    SplitReceived {
+        sender: String,
+        amount: Uint128,
+        msg: Binary,
+    },
+}
+
+/// Sent by the chain's ibc-hooks middleware.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    /// An ICS20 transfer sent with an `ibc_callback` memo naming this
+    /// contract was acknowledged or timed out.
+    IbcLifecycleComplete(IbcLifecycleComplete),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcLifecycleComplete {
+    IbcAck {
+        channel: String,
+        sequence: u64,
+        ack: String,
+        success: bool,
+    },
+    IbcTimeout {
+        channel: String,
+        sequence: u64,
+    },
+}
+
+/// Sent by the counterparty contract to split `amount` of `sender`'s escrow.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SplitPacket {
+    pub sender: String,
+    pub amount: Uint128,
+    pub recipient_1: String,
+    pub recipient_2: String,
+}
+
+/// The acknowledgement this contract writes for split packets, in the
+/// standard IBC format.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcAck {
+    Result(Binary),
+    Error(String),
+}
+
+/// Vests linearly from `start` to `end`. Nothing vests before `cliff`, at
+/// which point everything vested since `start` is released at once.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub struct Vesting {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub cliff: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum ForwardPreference {
+    /// Send credits straight to the sender.
+    Withdraw {},
+    /// Send credits on to another address.
+    ForwardTo { address: String },
+    /// Accumulate, then pay out the whole balance once it reaches `amount`.
+    Threshold { amount: Uint128 },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SplitRecipient {
+    pub payee: Payee,
+    pub shares: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Payee {
+    Address(String),
+    /// Passes the share on to another registered split.
+    Split(u64),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct BatchTransferItem {
+    /// Split evenly between the two recipients; fees come on top.
+    pub amount: Uint128,
+    pub recipient_1: String,
+    pub recipient_2: String,
 }
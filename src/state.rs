@@ -1,8 +1,458 @@
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Decimal, IbcEndpoint, Timestamp, Uint128};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, SnapshotMap, Strategy};
+use cw_utils::{Duration, Expiration};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::msg::{BatchFeeMode, CancelFeeMode, HookFailureMode, InternalFeeMode, Vesting};
 
 
 pub const OWNER: Item<Addr> = Item::new("owner");
 pub const COIN_DENOM: Item<String> = Item::new("coin_denom");
 pub const FEE: Item<Uint128> = Item::new("fee");
-pub const BALANCE: Map<&Addr, Uint128> = Map::new("balance");
+pub const LEGACY_ATTRIBUTES: Item<bool> = Item::new("legacy_attributes");
+pub const BATCH_FEE_MODE: Item<BatchFeeMode> = Item::new("batch_fee_mode");
+pub const INTERNAL_FEE_MODE: Item<InternalFeeMode> = Item::new("internal_fee_mode");
+pub const CANCEL_FEE_MODE: Item<CancelFeeMode> = Item::new("cancel_fee_mode");
+pub const HOOK_FAILURE_MODE: Item<HookFailureMode> = Item::new("hook_failure_mode");
+pub const KEEPER_BOUNTY: Item<Uint128> = Item::new("keeper_bounty");
+/// Withdrawable balances, snapshotted every block so past balances can be
+/// queried by height.
+pub const BALANCE: SnapshotMap<&Addr, Uint128> = SnapshotMap::new(
+    "balance",
+    "balance__checkpoints",
+    "balance__changelog",
+    Strategy::EveryBlock,
+);
+
+/// A completed `Transfer`, kept so the split can be looked up after the
+/// response is gone.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TransferRecord {
+    pub id: u64,
+    pub sender: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    /// Amount credited to each recipient.
+    pub recipient_amount: Uint128,
+    pub fee: Uint128,
+    pub sender_charged: Uint128,
+    pub block_height: u64,
+    pub block_time: Timestamp,
+    /// Set when the recipients' shares are pending claims that the sender can
+    /// reclaim once this passes.
+    #[serde(default)]
+    pub claim_deadline: Option<Expiration>,
+    /// Until this passes the sender can `CancelTransfer`.
+    #[serde(default)]
+    pub cancel_deadline: Option<Expiration>,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub vesting: Option<Vesting>,
+}
+
+/// Both recipient slots get their own index, so a lookup by recipient has to
+/// merge the two.
+pub struct TransferIndexes<'a> {
+    pub sender: MultiIndex<'a, Addr, TransferRecord, u64>,
+    pub recipient_1: MultiIndex<'a, Addr, TransferRecord, u64>,
+    pub recipient_2: MultiIndex<'a, Addr, TransferRecord, u64>,
+}
+
+impl<'a> IndexList<TransferRecord> for TransferIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<TransferRecord>> + '_> {
+        let v: Vec<&dyn Index<TransferRecord>> =
+            vec![&self.sender, &self.recipient_1, &self.recipient_2];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn transfers<'a>() -> IndexedMap<'a, u64, TransferRecord, TransferIndexes<'a>> {
+    let indexes = TransferIndexes {
+        sender: MultiIndex::new(|t: &TransferRecord| t.sender.clone(), "transfers", "transfers__sender"),
+        recipient_1: MultiIndex::new(
+            |t: &TransferRecord| t.recipient_1.clone(),
+            "transfers",
+            "transfers__recipient_1",
+        ),
+        recipient_2: MultiIndex::new(
+            |t: &TransferRecord| t.recipient_2.clone(),
+            "transfers",
+            "transfers__recipient_2",
+        ),
+    };
+    IndexedMap::new("transfers", indexes)
+}
+
+/// Id of the most recently recorded transfer; ids start at 1.
+pub const TRANSFER_COUNT: Item<u64> = Item::new("transfer_count");
+
+/// Where a split sends a share: an address's balance, or another split.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum SplitPayee {
+    Address(Addr),
+    Split(u64),
+}
+
+impl fmt::Display for SplitPayee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SplitPayee::Address(address) => write!(f, "{}", address),
+            SplitPayee::Split(split_id) => write!(f, "split:{}", split_id),
+        }
+    }
+}
+
+/// One payee of a split and its weight.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SplitShare {
+    pub payee: SplitPayee,
+    pub shares: u64,
+}
+
+/// A reusable set of recipients that anyone can pay into with `PayIntoSplit`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Split {
+    pub id: u64,
+    pub name: String,
+    pub owner: Addr,
+    pub recipients: Vec<SplitShare>,
+    /// Locked splits can no longer be updated.
+    pub locked: bool,
+}
+
+pub struct SplitIndexes<'a> {
+    pub owner: MultiIndex<'a, Addr, Split, u64>,
+}
+
+impl<'a> IndexList<Split> for SplitIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Split>> + '_> {
+        let v: Vec<&dyn Index<Split>> = vec![&self.owner];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn splits<'a>() -> IndexedMap<'a, u64, Split, SplitIndexes<'a>> {
+    let indexes = SplitIndexes {
+        owner: MultiIndex::new(|s: &Split| s.owner.clone(), "splits", "splits__owner"),
+    };
+    IndexedMap::new("splits", indexes)
+}
+
+/// Id of the most recently created split; ids start at 1.
+pub const SPLIT_COUNT: Item<u64> = Item::new("split_count");
+
+/// How much a spender may withdraw from an owner's balance, and until when.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Allowance {
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+/// Keyed by (owner, spender).
+pub const ALLOWANCES: Map<(&Addr, &Addr), Allowance> = Map::new("allowances");
+
+/// What happens to credits for an address that would rather not keep funds
+/// in the contract.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Forwarding {
+    /// Send each credit straight to the address itself.
+    Withdraw,
+    /// Send each credit to another address.
+    ForwardTo(Addr),
+    /// Keep crediting the balance, paying all of it out once it reaches this.
+    Threshold(Uint128),
+}
+
+pub const FORWARDING: Map<&Addr, Forwarding> = Map::new("forwarding");
+
+/// A recipient's share of a transfer waiting on the recipient, either to be
+/// claimed before a claim deadline or to be accepted.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PendingClaim {
+    pub transfer_id: u64,
+    pub sender: Addr,
+    pub recipient: Addr,
+    pub amount: Uint128,
+    pub expires: Expiration,
+}
+
+pub struct ClaimIndexes<'a> {
+    pub sender: MultiIndex<'a, Addr, PendingClaim, (u64, Addr)>,
+    pub recipient: MultiIndex<'a, Addr, PendingClaim, (u64, Addr)>,
+}
+
+impl<'a> IndexList<PendingClaim> for ClaimIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<PendingClaim>> + '_> {
+        let v: Vec<&dyn Index<PendingClaim>> = vec![&self.sender, &self.recipient];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Pending shares keyed by (transfer id, recipient).
+pub type PendingMap<'a> = IndexedMap<'a, (u64, &'a Addr), PendingClaim, ClaimIndexes<'a>>;
+
+pub fn claims<'a>() -> PendingMap<'a> {
+    let indexes = ClaimIndexes {
+        sender: MultiIndex::new(
+            |c: &PendingClaim| c.sender.clone(),
+            "claims",
+            "claims__sender",
+        ),
+        recipient: MultiIndex::new(
+            |c: &PendingClaim| c.recipient.clone(),
+            "claims",
+            "claims__recipient",
+        ),
+    };
+    IndexedMap::new("claims", indexes)
+}
+
+/// The part of a cancellable transfer's credit to an address that is still
+/// in their balance. `fee` is the owner's fee, so an owner who is also a
+/// recipient can be refunded each separately.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct CancellableCredit {
+    pub credited: Uint128,
+    pub fee: Uint128,
+    /// When the transfer's cancel window closes.
+    pub expires: Expiration,
+}
+
+/// Unspent credits from cancellable transfers, keyed by (address, transfer
+/// id) so spending from a balance can walk the address's credits in order.
+pub const CANCELLABLE_CREDITS: Map<(&Addr, u64), CancellableCredit> =
+    Map::new("cancellable_credits");
+
+/// A recipient's share of a vesting transfer. `released` is how much has
+/// vested and been moved into their balance so far.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct VestingGrant {
+    pub transfer_id: u64,
+    pub total: Uint128,
+    pub released: Uint128,
+    pub schedule: Vesting,
+}
+
+/// Keyed by (recipient, transfer id).
+pub const VESTING: Map<(&Addr, u64), VestingGrant> = Map::new("vesting");
+
+/// Recipients that must accept incoming transfers, and how long they have to
+/// do so before the funds go back to the sender.
+pub const ACCEPTANCE_WINDOWS: Map<&Addr, Duration> = Map::new("acceptance_windows");
+
+/// Transfers waiting for the recipient to accept or reject them.
+pub fn acceptances<'a>() -> PendingMap<'a> {
+    let indexes = ClaimIndexes {
+        sender: MultiIndex::new(
+            |c: &PendingClaim| c.sender.clone(),
+            "acceptances",
+            "acceptances__sender",
+        ),
+        recipient: MultiIndex::new(
+            |c: &PendingClaim| c.recipient.clone(),
+            "acceptances",
+            "acceptances__recipient",
+        ),
+    };
+    IndexedMap::new("acceptances", indexes)
+}
+
+/// Pays each recipient `rate_per_second` from `start` to `end`, funded up
+/// front by the sender.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Stream {
+    pub id: u64,
+    pub sender: Addr,
+    pub recipients: Vec<Addr>,
+    pub rate_per_second: Uint128,
+    pub start: Timestamp,
+    /// Pushed back by top-ups and brought forward by cancelling.
+    pub end: Timestamp,
+}
+
+pub const STREAMS: Map<u64, Stream> = Map::new("streams");
+pub const STREAM_COUNT: Item<u64> = Item::new("stream_count");
+/// How much of each stream has been moved into a recipient's balance, keyed
+/// by (recipient, stream id).
+pub const STREAMED: Map<(&Addr, u64), Uint128> = Map::new("streamed");
+
+/// A prefunded recurring `Transfer`, paid out one instalment at a time by
+/// whoever calls `ExecuteDue` once it falls due.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Schedule {
+    pub id: u64,
+    pub sender: Addr,
+    /// Charged per instalment, as `transfer_amount` is for a `Transfer`.
+    pub amount: Uint128,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    pub interval: Duration,
+    pub remaining: u32,
+    pub next_due: Expiration,
+    /// Paid to the keeper for each instalment, fixed when the schedule is
+    /// funded.
+    pub bounty: Uint128,
+}
+
+pub const SCHEDULES: Map<u64, Schedule> = Map::new("schedules");
+pub const SCHEDULE_COUNT: Item<u64> = Item::new("schedule_count");
+
+/// A planned `Transfer` held in escrow until someone reveals the preimage of
+/// `hash` before `expires`. Nothing is credited, fee included, until then.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HashLock {
+    pub id: u64,
+    pub sender: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    pub recipient_amount: Uint128,
+    pub fee: Uint128,
+    /// Hex encoded SHA-256 hash.
+    pub hash: String,
+    pub expires: Expiration,
+}
+
+pub const HASH_LOCKS: Map<u64, HashLock> = Map::new("hash_locks");
+pub const HASH_LOCK_COUNT: Item<u64> = Item::new("hash_lock_count");
+
+/// Funds for a two-recipient split held until the arbiter (and, with
+/// `joint_approval`, the sender too) releases them, or refunded on dispute.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Escrow {
+    pub id: u64,
+    pub sender: Addr,
+    pub arbiter: Addr,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    pub joint_approval: bool,
+    /// Still held, shared evenly between the recipients as it is released.
+    pub remaining: Uint128,
+    pub released: Uint128,
+    pub refunded: Uint128,
+    /// A release approved by one party and waiting for the other, when
+    /// approval is joint.
+    pub pending_release: Option<PendingRelease>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PendingRelease {
+    pub amount: Uint128,
+    pub approved_by: Addr,
+}
+
+pub const ESCROWS: Map<u64, Escrow> = Map::new("escrows");
+pub const ESCROW_COUNT: Item<u64> = Item::new("escrow_count");
+
+/// A payment request any payer can settle with `PayInvoice`, which runs it
+/// as a `Transfer` from the payer.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Invoice {
+    pub id: u64,
+    pub creator: Addr,
+    /// Charged to the payer, as `transfer_amount` is for a `Transfer`.
+    pub amount: Uint128,
+    pub denom: String,
+    pub recipient_1: Addr,
+    pub recipient_2: Addr,
+    pub expires: Expiration,
+    pub paid_by: Option<Addr>,
+    pub transfer_id: Option<u64>,
+}
+
+pub const INVOICES: Map<u64, Invoice> = Map::new("invoices");
+pub const INVOICE_COUNT: Item<u64> = Item::new("invoice_count");
+
+/// Set by the owner to keep funds above `buffer` delegated to `validator`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct StakingConfig {
+    pub validator: String,
+    pub buffer: Uint128,
+}
+
+pub const STAKING: Item<StakingConfig> = Item::new("staking");
+/// Total the contract has delegated.
+pub const DELEGATED: Item<Uint128> = Item::new("delegated");
+/// Withdrawals waiting on unbonded funds, by who they are paid to.
+pub const QUEUED_WITHDRAWALS: Map<&Addr, Uint128> = Map::new("queued_withdrawals");
+pub const QUEUED_TOTAL: Item<Uint128> = Item::new("queued_total");
+pub const UNBONDING_PERIOD: Item<Duration> = Item::new("unbonding_period");
+/// When the undelegation sent by turning staking off has unbonded. Until then
+/// payouts are still checked against what the contract holds.
+pub const UNBONDING_UNTIL: Item<Expiration> = Item::new("unbonding_until");
+
+/// A withdrawal waiting on unbonding funds. Entries are claimed in id order
+/// once they mature.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct QueuedWithdrawal {
+    pub recipient: Addr,
+    pub amount: Uint128,
+    pub matures: Expiration,
+}
+
+pub const WITHDRAWAL_QUEUE: Map<u64, QueuedWithdrawal> = Map::new("withdrawal_queue");
+pub const WITHDRAWAL_QUEUE_COUNT: Item<u64> = Item::new("withdrawal_queue_count");
+/// Funds held for escrows, hash locks, streams, schedules, vesting grants,
+/// remote escrows and pending shares. They are owed to someone other than a
+/// balance holder, so they are never delegated.
+pub const RESERVED: Item<Uint128> = Item::new("reserved");
+
+/// Sum of every `BALANCE`, which staking rewards are shared across.
+pub const TOTAL_BALANCE: Item<Uint128> = Item::new("total_balance");
+/// Rewards earned per coin of balance since the contract started staking.
+pub const REWARD_PER_SHARE: Item<Decimal> = Item::new("reward_per_share");
+/// Block height at which each validator's accrued rewards were last booked.
+/// Every staking message withdraws them, so they are only booked once a
+/// block.
+pub const REWARDS_BOOKED: Map<&str, u64> = Map::new("rewards_booked");
+
+/// Rewards settled to an address up to `index`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct RewardInfo {
+    pub index: Decimal,
+    pub pending: Uint128,
+}
+
+pub const REWARDS: Map<&Addr, RewardInfo> = Map::new("rewards");
+
+/// Balance each address has delegated itself, by validator.
+pub const USER_DELEGATIONS: Map<(&Addr, &str), Uint128> = Map::new("user_delegations");
+/// Sum of `USER_DELEGATIONS`, by validator.
+pub const USER_DELEGATED: Map<&str, Uint128> = Map::new("user_delegated");
+/// Rewards earned per coin users have delegated themselves, by validator.
+pub const DELEGATION_REWARD_PER_SHARE: Map<&str, Decimal> =
+    Map::new("delegation_reward_per_share");
+/// `DELEGATION_REWARD_PER_SHARE` each user delegation was last settled at.
+pub const DELEGATION_REWARD_INDEX: Map<(&Addr, &str), Decimal> =
+    Map::new("delegation_reward_index");
+
+/// A withdrawal sent over IBC, kept until its packet is acknowledged.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct IbcWithdrawal {
+    pub sender: Addr,
+    pub channel_id: String,
+    pub remote_address: String,
+    pub amount: Uint128,
+}
+
+/// The withdrawal being sent, until the transfer's reply gives its sequence.
+pub const IBC_SENDING: Item<IbcWithdrawal> = Item::new("ibc_sending");
+/// Withdrawals in flight, by source channel and packet sequence.
+pub const IBC_WITHDRAWALS: Map<(&str, u64), IbcWithdrawal> = Map::new("ibc_withdrawals");
+
+/// Open channels, with the counterparty's endpoint.
+pub const IBC_CHANNELS: Map<&str, IbcEndpoint> = Map::new("ibc_channels");
+/// Port of the counterpart contract, the only one allowed to open a channel.
+pub const IBC_COUNTERPARTY_PORT: Item<String> = Item::new("ibc_counterparty_port");
+
+/// Coins held for an account on another chain. Once the channel closes,
+/// whatever is left can be refunded to the funder.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RemoteEscrow {
+    pub funder: Addr,
+    pub amount: Uint128,
+}
+
+/// Funds held for accounts on other chains, by channel and remote sender.
+pub const REMOTE_ESCROWS: Map<(&str, &str), RemoteEscrow> = Map::new("remote_escrows");